@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::Read;
 use std::os::raw::c_uint;
 use std::str::{from_utf8, FromStr};
+use std::time::Duration;
 
 use directories::ProjectDirs;
 
@@ -11,9 +12,10 @@ use serde::{Deserialize, Deserializer};
 
 use lanta::keysym::*;
 use lanta::layout::*;
+use lanta::rules::Rule;
 use lanta::{
-    cmd, Center, Direction, Group, Lanta, Line, ModKey, NextWindow, Result as LantaResult,
-    Viewport, WindowId,
+    cmd, ipc, Center, Config as LantaConfig, Direction, Group, GroupPolicy, GroupSelector, Lanta,
+    Line, ModKey, NextWindow, Result as LantaResult, Viewport, WindowId,
 };
 
 #[derive(Deserialize, Debug)]
@@ -70,22 +72,53 @@ impl NextWindow<WindowId> for NextWindowStyle {
 #[derive(Deserialize, Debug)]
 enum Command {
     CloseFocused,
+    KillFocused,
     Focus(Style, Dir),
+    FocusUrgent,
     Swap(Style, Dir),
     GroupNext,
+    GoToGroup(GroupSelector),
+    MoveToGroup {
+        group: GroupSelector,
+        /// Whether to switch to the group as well.
+        #[serde(default)]
+        follow: bool,
+    },
     GroupPrev,
+    GroupLast,
+    AddGroup {
+        name: String,
+        #[serde(default)]
+        layout: Option<String>,
+    },
+    RenameGroup {
+        group: GroupSelector,
+        name: String,
+    },
+    RemoveGroup(GroupSelector),
+    ReorderGroup {
+        group: GroupSelector,
+        index: usize,
+    },
     MoveToNextGroup,
     MoveToPrevGroup,
     RotateCrtc,
     RotateLayout,
+    PrevLayout,
+    SetLayout(String),
     RotateFocus,
+    ToggleFloating,
     Spawn(Vec<String>),
+    Reload,
+    Restart,
 }
 
 impl Into<cmd::Command> for Command {
     fn into(self) -> cmd::Command {
         match self {
             Command::CloseFocused => cmd::lazy::close_focused_window(),
+            Command::KillFocused => cmd::lazy::kill_focused_window(),
+            Command::FocusUrgent => cmd::lazy::focus_urgent(),
             Command::Focus(s, d) => {
                 let style: NextWindowStyle = s.into();
                 cmd::lazy::focus_in(style, d.into())
@@ -95,17 +128,31 @@ impl Into<cmd::Command> for Command {
                 cmd::lazy::swap_in(style, d.into())
             }
             Command::GroupNext => cmd::lazy::next_group(),
+            Command::GoToGroup(group) => cmd::lazy::go_to_group(group),
+            Command::MoveToGroup { group, follow } => {
+                cmd::lazy::move_window_to_group(group, follow)
+            }
             Command::GroupPrev => cmd::lazy::prev_group(),
+            Command::GroupLast => cmd::lazy::group_last(),
+            Command::AddGroup { name, layout } => cmd::lazy::add_group(name, layout),
+            Command::RenameGroup { group, name } => cmd::lazy::rename_group(group, name),
+            Command::RemoveGroup(group) => cmd::lazy::remove_group(group),
+            Command::ReorderGroup { group, index } => cmd::lazy::reorder_group(group, index),
             Command::MoveToNextGroup => cmd::lazy::move_window_to_next_group(),
             Command::MoveToPrevGroup => cmd::lazy::move_window_to_prev_group(),
             Command::RotateCrtc => cmd::lazy::rotate_crtc(),
             Command::RotateLayout => cmd::lazy::layout_next(),
+            Command::PrevLayout => cmd::lazy::layout_prev(),
+            Command::SetLayout(name) => cmd::lazy::set_layout(name),
             Command::RotateFocus => cmd::lazy::rotate_focus_in_group(),
+            Command::ToggleFloating => cmd::lazy::toggle_floating(),
             Command::Spawn(cmd) => {
                 let mut command = std::process::Command::new(&cmd[0]);
                 command.args(&cmd[1..]);
                 cmd::lazy::spawn(command)
             }
+            Command::Reload => cmd::lazy::reload(),
+            Command::Restart => cmd::lazy::restart(),
         }
     }
 }
@@ -147,6 +194,16 @@ impl FromStr for KeyInner {
             "x" => XK_x,
             "y" => XK_y,
             "z" => XK_z,
+            "0" => XK_0,
+            "1" => XK_1,
+            "2" => XK_2,
+            "3" => XK_3,
+            "4" => XK_4,
+            "5" => XK_5,
+            "6" => XK_6,
+            "7" => XK_7,
+            "8" => XK_8,
+            "9" => XK_9,
             "space" => XK_space,
             "enter" => XK_Return,
             "tab" => XK_Tab,
@@ -211,6 +268,9 @@ impl Into<Box<dyn Layout<WindowId>>> for LayoutSelect {
 struct GroupDesc {
     name: String,
     layout: String,
+    /// The layouts to cycle through, if not all of them.
+    #[serde(default)]
+    layouts: Vec<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -218,6 +278,18 @@ struct Config {
     keys: HashMap<KeyInner, Command>,
     layouts: Vec<LayoutSelect>,
     groups: Vec<GroupDesc>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+    /// Seconds to wait for a closed window to go away before killing its
+    /// client.
+    close_timeout: Option<f64>,
+    /// What to do when switching to a group shown on another screen.
+    #[serde(default)]
+    group_policy: GroupPolicy,
+    /// Whether going to the group that is already shown goes back to the
+    /// previous one.
+    #[serde(default)]
+    back_and_forth: bool,
 }
 
 #[derive(Debug)]
@@ -229,8 +301,7 @@ impl std::fmt::Display for NoProjectDir {
 }
 impl std::error::Error for NoProjectDir {}
 
-fn main() -> LantaResult<()> {
-    env_logger::init();
+fn load_config() -> LantaResult<LantaConfig> {
     let dirs = ProjectDirs::from("org", "foo", "lanta").ok_or(NoProjectDir {})?;
     let mut config_path = dirs.config_dir().to_path_buf();
     config_path.push("lanta.yaml");
@@ -241,7 +312,11 @@ fn main() -> LantaResult<()> {
         keys,
         layouts,
         groups,
-    } = serde_yaml::from_str(from_utf8(&buffer).unwrap())?;
+        rules,
+        close_timeout,
+        group_policy,
+        back_and_forth,
+    } = serde_yaml::from_str(from_utf8(&buffer)?)?;
     let keys: Vec<_> = keys
         .into_iter()
         .map(|(k, v)| (k.mods, k.key, v.into()))
@@ -249,10 +324,41 @@ fn main() -> LantaResult<()> {
     let layouts: Vec<_> = layouts.into_iter().map(|l| l.into()).collect();
     let groups: Vec<_> = groups
         .into_iter()
-        .map(|g| Group::new(g.name, &g.layout, &layouts))
+        .map(|g| Group::new(g.name, &g.layout, &layouts).with_layouts(&g.layouts, &layouts))
         .collect();
+    Ok(LantaConfig {
+        keys: keys.into(),
+        groups,
+        layouts,
+        rules,
+        close_timeout: close_timeout.map(Duration::try_from_secs_f64).transpose()?,
+        group_policy,
+        back_and_forth,
+    })
+}
+
+fn main() -> LantaResult<()> {
+    env_logger::init();
+    let LantaConfig {
+        keys,
+        groups,
+        layouts,
+        rules,
+        close_timeout,
+        group_policy,
+        back_and_forth,
+    } = load_config()?;
 
-    Lanta::new(keys, groups, layouts)?.run();
+    let mut wm = Lanta::new(keys, groups, layouts, rules)?;
+    wm.set_close_timeout(close_timeout);
+    wm.set_group_policy(group_policy);
+    wm.set_back_and_forth(back_and_forth);
+    wm.listen(ipc::socket_path()?, |text| {
+        let command: Command = serde_yaml::from_str(text)?;
+        Ok(command.into())
+    })?;
+    wm.reload_with(load_config)?;
+    wm.run();
 
     Ok(())
 }
@@ -0,0 +1,55 @@
+use std::env;
+use std::process;
+
+use lanta::ipc::{self, Notification, Reply, Request};
+use lanta::Result;
+
+fn usage() -> ! {
+    eprintln!("Usage: lantactl COMMAND");
+    eprintln!("       lantactl get-tree");
+    eprintln!("       lantactl subscribe");
+    eprintln!();
+    eprintln!("COMMAND is written as it would be in the keys section of lanta.yaml, e.g.");
+    eprintln!("    lantactl GroupNext");
+    eprintln!("    lantactl 'Focus: [Center, Left]'");
+    process::exit(2);
+}
+
+fn fail(error: String) -> ! {
+    eprintln!("lantactl: {}", error);
+    process::exit(1);
+}
+
+/// Prints each notification from the window manager as a line of JSON, until
+/// the window manager goes away.
+fn subscribe() -> Result<()> {
+    let mut client = ipc::connect(ipc::socket_path()?)?;
+    client.send(&Request::Subscribe)?;
+    if let Reply::Error(e) = client.receive()? {
+        fail(e);
+    }
+    loop {
+        let notification: Notification = client.receive()?;
+        println!("{}", serde_json::to_string(&notification)?);
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        usage();
+    }
+    let request = match args[0].as_str() {
+        "get-tree" => Request::GetTree,
+        "subscribe" => return subscribe(),
+        _ => Request::Command(args.join(" ")),
+    };
+    match ipc::send(ipc::socket_path()?, &request)? {
+        Reply::Ok => Ok(()),
+        Reply::Tree(tree) => {
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+            Ok(())
+        }
+        Reply::Error(e) => fail(e),
+    }
+}
@@ -1,9 +1,9 @@
-use lanta::{Connection, Event, Result};
+use lanta::{Connection, Event, Result, XConnection};
 
 fn main() -> Result<()> {
     let conn = Connection::connect()?;
     println!("{:?}", conn.list_crtc()?);
-    for event in conn.get_event_loop() {
+    for event in conn.get_event_loop(&[]) {
         match event {
             Event::CrtcChange(cc) => println!("{:?}", cc),
             _ => (),
@@ -1,24 +1,26 @@
 use std::rc::Rc;
 
+use crate::x::Connection;
 use crate::Lanta;
 use std::io::Result;
 
-pub type Command = Rc<dyn Fn(&mut Lanta) -> Result<()>>;
+pub type Command<C = Connection> = Rc<dyn Fn(&mut Lanta<C>) -> Result<()>>;
 
 /// Lazy-functions which return a `Command` to do the requested action.
 // TODO: Consider offering non-lazy versions and then having simple lazy
 // wrappers for them.
 pub mod lazy {
 
+    use std::io;
     use std::process;
     use std::rc::Rc;
     use std::sync::Mutex;
 
     use super::Command;
-    use crate::{Direction, NextWindow, WindowId};
+    use crate::{Direction, GroupSelector, NextWindow, WindowId, XConnection};
 
     /// Rotate the active Crtc
-    pub fn rotate_crtc() -> Command {
+    pub fn rotate_crtc<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|ref mut wm| {
             wm.rotate_crtc();
             Ok(())
@@ -26,21 +28,43 @@ pub mod lazy {
     }
 
     /// Closes the currently focused window.
-    pub fn close_focused_window() -> Command {
+    pub fn close_focused_window<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|ref mut wm| {
             wm.close_focused();
             Ok(())
         })
     }
 
-    pub fn focus_in<S: NextWindow<WindowId> + 'static>(style: S, dir: Direction) -> Command {
+    pub fn kill_focused_window<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|ref mut wm| {
+            wm.kill_focused();
+            Ok(())
+        })
+    }
+
+    pub fn focus_urgent<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|ref mut wm| {
+            wm.focus_urgent();
+            Ok(())
+        })
+    }
+
+    pub fn focus_in<C, S>(style: S, dir: Direction) -> Command<C>
+    where
+        C: XConnection + 'static,
+        S: NextWindow<WindowId> + 'static,
+    {
         Rc::new(move |ref mut wm| {
             wm.focus_in_direction(&style, &dir);
             Ok(())
         })
     }
 
-    pub fn swap_in<S: NextWindow<WindowId> + 'static>(style: S, dir: Direction) -> Command {
+    pub fn swap_in<C, S>(style: S, dir: Direction) -> Command<C>
+    where
+        C: XConnection + 'static,
+        S: NextWindow<WindowId> + 'static,
+    {
         Rc::new(move |ref mut wm| {
             wm.swap_in_direction(&style, &dir);
             Ok(())
@@ -48,7 +72,7 @@ pub mod lazy {
     }
 
     /// Moves the focus to the previous window in the current group's stack.
-    pub fn rotate_focus_in_group() -> Command {
+    pub fn rotate_focus_in_group<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|ref mut wm| {
             wm.rotate_focus_in_group();
             Ok(())
@@ -56,17 +80,33 @@ pub mod lazy {
     }
 
     /// Cycles to the next layout of the current group.
-    pub fn layout_next() -> Command {
+    pub fn layout_next<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|ref mut wm| {
             wm.group_cycle_layouts();
             Ok(())
         })
     }
 
+    /// Cycles back to the previous layout of the current group.
+    pub fn layout_prev<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|wm| {
+            wm.group_prev_layout();
+            Ok(())
+        })
+    }
+
+    /// Switches the current group to the named layout.
+    pub fn set_layout<C: XConnection + 'static>(name: String) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.set_layout(&name)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
     /// Spawns the specified command.
     ///
     /// The returned `Command` will spawn the `Command` each time it is called.
-    pub fn spawn(command: process::Command) -> Command {
+    pub fn spawn<C: XConnection + 'static>(command: process::Command) -> Command<C> {
         let mutex = Mutex::new(command);
         Rc::new(move |ref mut wm| {
             let mut command = mutex.lock().unwrap();
@@ -77,22 +117,105 @@ pub mod lazy {
         })
     }
 
-    pub fn next_group() -> Command {
+    /// Reads the configuration again, keeping every window in its group.
+    pub fn reload<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|wm| wm.reload().map_err(|e| io::Error::other(e.to_string())))
+    }
+
+    pub fn toggle_floating<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|wm| {
+            wm.toggle_floating();
+            Ok(())
+        })
+    }
+
+    /// Restarts the window manager in place, keeping every window in its
+    /// group.
+    pub fn restart<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|wm| wm.restart().map_err(|e| io::Error::other(e.to_string())))
+    }
+
+    /// Shows the selected group on the current screen.
+    pub fn go_to_group<C: XConnection + 'static>(group: GroupSelector) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.go_to_group(&group)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    /// Adds a group at the end, with the named layout or else the first one.
+    pub fn add_group<C: XConnection + 'static>(name: String, layout: Option<String>) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.add_group(&name, layout.as_deref())
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    pub fn rename_group<C: XConnection + 'static>(
+        group: GroupSelector,
+        name: String,
+    ) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.rename_group(&group, &name)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    /// Removes the selected group, moving its windows to a neighbor.
+    pub fn remove_group<C: XConnection + 'static>(group: GroupSelector) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.remove_group(&group)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    /// Moves the selected group to another position.
+    pub fn reorder_group<C: XConnection + 'static>(
+        group: GroupSelector,
+        index: usize,
+    ) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.reorder_group(&group, index)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    /// Moves the focused window to the selected group, switching to it too if
+    /// `follow` is set.
+    pub fn move_window_to_group<C: XConnection + 'static>(
+        group: GroupSelector,
+        follow: bool,
+    ) -> Command<C> {
+        Rc::new(move |wm| {
+            wm.move_focused_to(&group, follow)
+                .map_err(|e| io::Error::other(e.to_string()))
+        })
+    }
+
+    pub fn next_group<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|wm| {
             wm.next_group();
             Ok(())
         })
     }
 
-    pub fn prev_group() -> Command {
+    pub fn prev_group<C: XConnection + 'static>() -> Command<C> {
         Rc::new(|wm| {
             wm.prev_group();
             Ok(())
         })
     }
 
+    /// Goes back to the group the current screen showed before.
+    pub fn group_last<C: XConnection + 'static>() -> Command<C> {
+        Rc::new(|wm| {
+            wm.group_last();
+            Ok(())
+        })
+    }
+
     /// Moves the focused window on the active group to another group.
-    pub fn move_window_to_next_group() -> Command {
+    pub fn move_window_to_next_group<C: XConnection + 'static>() -> Command<C> {
         Rc::new(move |wm| {
             wm.move_focused_to_next_group();
             Ok(())
@@ -100,7 +223,7 @@ pub mod lazy {
     }
 
     /// Moves the focused window on the active group to another group.
-    pub fn move_window_to_prev_group() -> Command {
+    pub fn move_window_to_prev_group<C: XConnection + 'static>() -> Command<C> {
         Rc::new(move |wm| {
             wm.move_focused_to_prev_group();
             Ok(())
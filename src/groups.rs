@@ -1,14 +1,21 @@
 use std::borrow::Cow;
 
+use serde::Deserialize;
+
 use crate::layout::Layout;
-use crate::x::WindowId;
+use crate::x::{Crtc, WindowId};
 
 type LayoutId = usize;
 
 pub struct Group {
     name: Cow<'static, str>,
     pub layout_id: LayoutId,
+    /// The layouts that the group cycles through, in order. Empty to cycle
+    /// through every layout.
+    pub layout_cycle: Vec<LayoutId>,
     pub focused_window: Option<WindowId>,
+    /// The screen the group was last shown on.
+    pub last_crtc: Option<Crtc>,
 }
 
 impl Group {
@@ -23,11 +30,124 @@ impl Group {
         Group {
             name: name.into(),
             layout_id,
+            layout_cycle: Vec::new(),
             focused_window: None,
+            last_crtc: None,
         }
     }
 
+    /// Makes the group cycle through only the named layouts, in the given
+    /// order. Names that aren't in `layouts` are ignored.
+    pub fn with_layouts<S, T>(mut self, names: &[S], layouts: &[Box<dyn Layout<T>>]) -> Group
+    where
+        S: AsRef<str>,
+    {
+        self.layout_cycle = names
+            .iter()
+            .filter_map(|name| layouts.iter().position(|l| l.name() == name.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Returns the layout after the current one in the group's cycle, or
+    /// before it if `forward` is false, out of `num_layouts` layouts.
+    pub fn step_layout(&self, num_layouts: usize, forward: bool) -> Option<LayoutId> {
+        let cycle: Vec<LayoutId> = if self.layout_cycle.is_empty() {
+            (0..num_layouts).collect()
+        } else {
+            self.layout_cycle.clone()
+        };
+        let len = cycle.len();
+        if len == 0 {
+            return None;
+        }
+        let idx = match cycle.iter().position(|&l| l == self.layout_id) {
+            Some(pos) if forward => (pos + 1) % len,
+            Some(pos) => (pos + len - 1) % len,
+            // A layout outside the cycle, chosen with `SetLayout`.
+            None if forward => 0,
+            None => len - 1,
+        };
+        Some(cycle[idx])
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn set_name<S: Into<Cow<'static, str>>>(&mut self, name: S) {
+        self.name = name.into();
+    }
+}
+
+/// What happens when switching to a group that is shown on another screen,
+/// or was last shown there.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum GroupPolicy {
+    /// Move the focus to the screen showing the group.
+    View,
+    /// Swap the group with the one on the current screen.
+    #[default]
+    Greedy,
+    /// Like `View`, and a hidden group is shown on the screen it was last
+    /// shown on, so that groups never move between screens.
+    Pinned,
+}
+
+/// Picks out a group by its position, counting from zero, or by its name.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum GroupSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl GroupSelector {
+    /// Returns the position of the selected group in `groups`, if there is
+    /// one.
+    pub fn find(&self, groups: &[Group]) -> Option<usize> {
+        match self {
+            GroupSelector::Index(idx) => Some(*idx).filter(|&idx| idx < groups.len()),
+            GroupSelector::Name(name) => groups.iter().position(|g| g.name() == name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::layout::{StackLayout, TiledLayout};
+
+    #[test]
+    fn layouts_cycle_through_the_group_list() {
+        let layouts: Vec<Box<dyn Layout<WindowId>>> = vec![
+            Box::new(StackLayout::new("stack", 0)),
+            Box::new(TiledLayout::new("tiled", 0)),
+            Box::new(StackLayout::new("monocle", 0)),
+        ];
+        let all = Group::new("all", "monocle", &layouts);
+        assert_eq!(all.step_layout(layouts.len(), true), Some(0));
+        assert_eq!(all.step_layout(layouts.len(), false), Some(1));
+
+        let mut code = Group::new("code", "monocle", &layouts)
+            .with_layouts(&["monocle", "missing", "stack"], &layouts);
+        assert_eq!(code.layout_cycle, vec![2, 0]);
+        assert_eq!(code.step_layout(layouts.len(), true), Some(0));
+        assert_eq!(code.step_layout(layouts.len(), false), Some(0));
+        code.layout_id = 1;
+        assert_eq!(code.step_layout(layouts.len(), true), Some(2));
+        assert_eq!(code.step_layout(layouts.len(), false), Some(0));
+    }
+
+    #[test]
+    fn selectors_are_read_as_numbers_or_names() {
+        let selectors: Vec<GroupSelector> = serde_yaml::from_str("[3, mail]").unwrap();
+        assert_eq!(
+            selectors,
+            vec![
+                GroupSelector::Index(3),
+                GroupSelector::Name("mail".to_owned())
+            ]
+        );
+    }
 }
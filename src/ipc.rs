@@ -0,0 +1,397 @@
+//! A Unix domain socket that allows Lanta to be controlled by other programs.
+//!
+//! Each connection carries a single `Request` and its `Reply`, both encoded as
+//! one line of JSON. After a `Subscribe` request the connection stays open,
+//! and a `Notification` is written to it, one per line, whenever the state of
+//! the window manager changes.
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::viewport::{Strut, Viewport};
+use crate::x::{CrtcInfo, WindowId};
+use crate::Result;
+
+/// How long the window manager will wait on a client before giving up on it.
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many bytes of notifications may wait for a subscriber to read them.
+const MAX_PENDING: usize = 1 << 20;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Request {
+    /// Run a command, written as it would be in the `keys` section of the
+    /// config file.
+    Command(String),
+    /// Describe everything the window manager knows about.
+    GetTree,
+    /// Receive a `Notification` for every future change.
+    Subscribe,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Reply {
+    Ok,
+    Error(String),
+    Tree(Tree),
+}
+
+/// A change in the state of the window manager, sent to subscribers.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Notification {
+    WindowManaged {
+        id: WindowId,
+        group: usize,
+    },
+    WindowUnmanaged {
+        id: WindowId,
+    },
+    WindowFocused {
+        id: WindowId,
+    },
+    /// A window started or stopped wanting attention.
+    WindowUrgency {
+        id: WindowId,
+        urgent: bool,
+    },
+    GroupFocused {
+        crtc: Option<u32>,
+        group: usize,
+    },
+    LayoutChanged {
+        group: usize,
+        layout: String,
+    },
+    CrtcAdded {
+        crtc: u32,
+        info: CrtcInfo,
+        group: usize,
+    },
+    CrtcChanged {
+        crtc: u32,
+        info: CrtcInfo,
+    },
+    CrtcRemoved {
+        crtc: u32,
+    },
+    /// Groups were added, removed, renamed or reordered.
+    GroupsChanged,
+    /// The key bindings, layouts and groups were replaced.
+    Reconfigured,
+}
+
+/// A snapshot of the window manager's state.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Tree {
+    pub groups: Vec<GroupInfo>,
+    pub windows: Vec<WindowInfo>,
+    pub crtcs: Vec<CrtcGroup>,
+    pub current_crtc: Option<u32>,
+    /// The windows that are currently visible, and where.
+    pub mapped: Vec<MappedInfo>,
+    pub docks: Vec<DockInfo>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GroupInfo {
+    pub name: String,
+    pub layout_id: usize,
+    pub layout: String,
+    pub focused_window: Option<WindowId>,
+    /// Whether any window in the group wants attention.
+    pub urgent: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WindowInfo {
+    pub id: WindowId,
+    pub group: usize,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub urgent: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CrtcGroup {
+    pub crtc: u32,
+    pub info: CrtcInfo,
+    pub group: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MappedInfo {
+    pub id: WindowId,
+    pub viewport: Viewport,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DockInfo {
+    pub id: WindowId,
+    pub strut: Option<Strut>,
+}
+
+/// Returns the path of the control socket.
+///
+/// This is `$LANTA_SOCKET` if it is set, otherwise `lanta/ipc-$DISPLAY.sock`
+/// in `$XDG_RUNTIME_DIR`, so that each display has its own.
+pub fn socket_path() -> Result<PathBuf> {
+    if let Some(path) = env::var_os("LANTA_SOCKET") {
+        return Ok(path.into());
+    }
+    let dirs = xdg::BaseDirectories::with_prefix("lanta")?;
+    let display = env::var("DISPLAY").ok();
+    Ok(dirs.place_runtime_file(socket_name(display.as_deref()))?)
+}
+
+fn socket_name(display: Option<&str>) -> String {
+    match display {
+        Some(display) if !display.is_empty() => {
+            format!("ipc-{}.sock", display.replace('/', "_"))
+        }
+        _ => "ipc.sock".to_owned(),
+    }
+}
+
+/// Connects to the window manager listening on `path`.
+pub fn connect<P: AsRef<Path>>(path: P) -> Result<Client> {
+    Ok(Client::new(UnixStream::connect(path)?)?)
+}
+
+/// Sends a single request to the window manager listening on `path`, and
+/// waits for its reply.
+pub fn send<P: AsRef<Path>>(path: P, request: &Request) -> Result<Reply> {
+    let mut client = connect(path)?;
+    client.send(request)?;
+    client.receive()
+}
+
+/// One end of a connection to the control socket.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> io::Result<Client> {
+        let writer = stream.try_clone()?;
+        Ok(Client {
+            reader: BufReader::new(stream),
+            writer,
+        })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.writer.set_nonblocking(nonblocking)
+    }
+
+    pub fn send<T: Serialize>(&self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        (&self.writer).write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn receive<T>(&mut self) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed").into());
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// A client that receives a `Notification` for every change.
+///
+/// Writes to a subscriber never block, as one that can't keep up must not
+/// stall the window manager. Whatever doesn't fit in the socket waits until
+/// `flush()` is called again. A subscriber that falls more than `MAX_PENDING`
+/// bytes behind is sent nothing more, and is done with once everything before
+/// that has been written, so that it never receives half a line.
+pub struct Subscriber {
+    client: Client,
+    pending: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Subscriber {
+    pub fn new(client: Client) -> io::Result<Subscriber> {
+        client.set_nonblocking(true)?;
+        Ok(Subscriber {
+            client,
+            pending: Vec::new(),
+            overflowed: false,
+        })
+    }
+
+    pub fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        if !self.overflowed {
+            let mut line = serde_json::to_vec(message)?;
+            line.push(b'\n');
+            if self.pending.len() + line.len() > MAX_PENDING {
+                warn!("IPC subscriber fell behind, no longer notifying it");
+                self.overflowed = true;
+            } else {
+                self.pending.extend(line);
+            }
+        }
+        Ok(self.flush()?)
+    }
+
+    /// Writes as much of the pending notifications as the socket will take.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            match (&self.client.writer).write(&self.pending) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.pending.drain(..written);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether the subscriber should be dropped, having fallen behind and
+    /// since caught up with what it was sent.
+    pub fn is_finished(&self) -> bool {
+        self.overflowed && self.pending.is_empty()
+    }
+}
+
+/// The listening end of the control socket.
+///
+/// The socket file is removed when the server is dropped.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Server {
+    /// Listens on `path`, unless another process already does.
+    pub fn bind<P: Into<PathBuf>>(path: P) -> Result<Server> {
+        let path = path.into();
+        // A socket left behind by a previous instance would prevent binding,
+        // but one that is still in use belongs to another window manager.
+        if path.exists() {
+            match UnixStream::connect(&path) {
+                Ok(_) => {
+                    return Err(format!("{} is already in use", path.display()).into());
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    fs::remove_file(&path)?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        info!("Listening for commands on {}", path.display());
+        Ok(Server { listener, path })
+    }
+
+    /// Accepts every client that is waiting to connect, without blocking.
+    pub fn accept(&self) -> Vec<Client> {
+        let mut clients = Vec::new();
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _addr)) => match Server::setup_client(stream) {
+                    Ok(client) => clients.push(client),
+                    Err(e) => warn!("Could not set up IPC client: {}", e),
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Could not accept IPC client: {}", e);
+                    break;
+                }
+            }
+        }
+        clients
+    }
+
+    fn setup_client(stream: UnixStream) -> io::Result<Client> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        Client::new(stream)
+    }
+}
+
+impl AsRawFd for Server {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn sockets_are_named_after_the_display() {
+        assert_eq!(socket_name(Some(":1")), "ipc-:1.sock");
+        assert_eq!(socket_name(Some("host/unix:0")), "ipc-host_unix:0.sock");
+        assert_eq!(socket_name(None), "ipc.sock");
+    }
+
+    #[test]
+    fn subscribers_that_fall_behind_only_get_whole_lines() {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        theirs.set_nonblocking(true).unwrap();
+        let mut subscriber = Subscriber::new(Client::new(ours).unwrap()).unwrap();
+        let notification = Notification::CrtcRemoved { crtc: 1 };
+        while !subscriber.overflowed {
+            subscriber.send(&notification).unwrap();
+        }
+        let mut received = Vec::new();
+        while !subscriber.is_finished() {
+            let mut buf = [0; 4096];
+            match theirs.read(&mut buf) {
+                Ok(read) => received.extend_from_slice(&buf[..read]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                Err(e) => panic!("{}", e),
+            }
+            subscriber.flush().unwrap();
+        }
+        drop(subscriber);
+        theirs.set_nonblocking(false).unwrap();
+        theirs.read_to_end(&mut received).unwrap();
+        let received = String::from_utf8(received).unwrap();
+        assert!(received.ends_with('\n'));
+        for line in received.lines() {
+            let _: Notification = serde_json::from_str(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn bind_replaces_only_stale_sockets() {
+        let path = env::temp_dir().join(format!("lanta-bind-{}.sock", std::process::id()));
+        drop(UnixListener::bind(&path).unwrap());
+        let server = Server::bind(&path).unwrap();
+        assert!(Server::bind(&path).is_err());
+        assert!(path.exists());
+        drop(server);
+        assert!(!path.exists());
+    }
+}
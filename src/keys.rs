@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::os::raw::c_uint;
 
 use crate::cmd::Command;
+use crate::x::Connection;
 
 /// Represents a modifier key.
 #[allow(dead_code)]
@@ -62,22 +63,22 @@ impl KeyCombo {
     }
 }
 
-pub struct KeyHandlers {
-    hashmap: HashMap<KeyCombo, Command>,
+pub struct KeyHandlers<C = Connection> {
+    hashmap: HashMap<KeyCombo, Command<C>>,
 }
 
-impl KeyHandlers {
+impl<C> KeyHandlers<C> {
     pub fn key_combos(&self) -> Vec<&KeyCombo> {
         self.hashmap.keys().collect()
     }
 
-    pub fn get(&self, key_combo: &KeyCombo) -> Option<Command> {
+    pub fn get(&self, key_combo: &KeyCombo) -> Option<Command<C>> {
         self.hashmap.get(key_combo).cloned()
     }
 }
 
-impl From<Vec<(Vec<ModKey>, Key, Command)>> for KeyHandlers {
-    fn from(handlers: Vec<(Vec<ModKey>, Key, Command)>) -> KeyHandlers {
+impl<C> From<Vec<(Vec<ModKey>, Key, Command<C>)>> for KeyHandlers<C> {
+    fn from(handlers: Vec<(Vec<ModKey>, Key, Command<C>)>) -> KeyHandlers<C> {
         let mut hashmap = HashMap::new();
         for (modkeys, keysym, handler) in handlers {
             hashmap.insert(KeyCombo::new(&modkeys, keysym), handler);
@@ -1,33 +1,51 @@
 #[macro_use]
 extern crate log;
 
+use std::cmp;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
 use std::process::Child;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub mod cmd;
 mod groups;
+pub mod ipc;
 mod keys;
 pub mod layout;
 mod navigation;
+mod restart;
+pub mod rules;
 mod screen;
+mod signal;
 mod stack;
+mod timer;
 mod viewport;
 mod x;
 
-use crate::x::{Crtc, CrtcChange, WindowType};
+use crate::cmd::Command;
+use crate::ipc::{
+    CrtcGroup, DockInfo, GroupInfo, MappedInfo, Notification, Reply, Request, Tree, WindowInfo,
+};
+use crate::x::{Crtc, CrtcChange, WindowType, WmState};
 use keys::{KeyCombo, KeyHandlers};
 use layout::{Layout, MappedWindow};
+use rules::{Action, Rule, WindowProperties};
 use screen::{Dock, Screen};
 
-pub use groups::Group;
+pub use groups::{Group, GroupPolicy, GroupSelector};
 pub use keys::ModKey;
 pub use navigation::{Center, Direction, Line, NextWindow};
+pub use restart::SavedState;
 pub use stack::Stack;
-pub use viewport::Viewport;
-pub use x::{Connection, CrtcInfo, Event, WindowId};
+pub use viewport::{SizeHints, Viewport};
+pub use x::{
+    ConfigureRequest, Connection, CrtcInfo, Event, InputModel, StateAction, WindowId, WindowState,
+    XConnection,
+};
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
@@ -37,13 +55,86 @@ pub mod keysym {
 
 type GroupId = usize;
 
+/// Windows of these types float above the tiled windows instead of being
+/// given a place in the layout.
+const FLOATING_TYPES: &[WindowType] = &[
+    WindowType::Dialog,
+    WindowType::Splash,
+    WindowType::Utility,
+    WindowType::Toolbar,
+    WindowType::Menu,
+];
+
+/// Turns the text of a command received over IPC into a `Command`.
+pub type CommandParser<C> = Box<dyn Fn(&str) -> Result<Command<C>>>;
+
+struct Ipc<C> {
+    server: ipc::Server,
+    parse: CommandParser<C>,
+    subscribers: Vec<ipc::Subscriber>,
+}
+
+/// The parts of the window manager that are read from the configuration, and
+/// can be replaced by reloading it.
+pub struct Config<C = Connection> {
+    pub keys: KeyHandlers<C>,
+    pub groups: Vec<Group>,
+    pub layouts: Vec<Box<dyn Layout<WindowId>>>,
+    pub rules: Vec<Rule>,
+    /// How long a window has to close before its client is killed, if it
+    /// stops responding. `None` never kills a client.
+    pub close_timeout: Option<Duration>,
+    /// What happens when switching to a group shown on another screen.
+    pub group_policy: GroupPolicy,
+    /// Whether going to the group that is already shown goes back to the
+    /// previous one instead.
+    pub back_and_forth: bool,
+}
+
+/// Reads the configuration afresh.
+pub type ConfigLoader<C> = Box<dyn Fn() -> Result<Config<C>>>;
+
+struct Reload<C> {
+    load: ConfigLoader<C>,
+    hangup: signal::Hangup,
+}
+
 struct Window {
     id: WindowId,
     group: GroupId,
+    /// The geometry of a floating window, relative to the screen showing its
+    /// group. `None` if the window is tiled.
+    floating: Option<Viewport>,
+    /// Whether the window covers the whole of its screen.
+    fullscreen: bool,
+    /// Constrains the window's size when it is tiled.
+    size_hints: SizeHints,
+    /// The window this one is a dialog for.
+    transient_for: Option<WindowId>,
+    /// Whether the window must be dismissed before `transient_for` can have
+    /// the focus.
+    modal: bool,
+    /// Whether the client set the urgency bit in its `WM_HINTS`.
+    urgent_hint: bool,
+    /// Whether the client asked for `_NET_WM_STATE_DEMANDS_ATTENTION`.
+    demands_attention: bool,
+}
+
+/// A window that was asked to close, and will be killed if it hasn't by
+/// `deadline`.
+struct Closing {
+    id: WindowId,
+    deadline: Instant,
+    /// Whether the client answered a ping since it was asked, and so is
+    /// presumably asking the user what to do rather than hung.
+    responsive: bool,
 }
 
 trait InGroup {
     fn in_group(&self, group_id: GroupId) -> Vec<WindowId>;
+
+    /// Like `in_group`, leaving out floating and fullscreen windows.
+    fn tiled_in_group(&self, group_id: GroupId) -> Vec<WindowId>;
 }
 
 impl InGroup for Vec<Window> {
@@ -53,30 +144,78 @@ impl InGroup for Vec<Window> {
             .map(|w| w.id)
             .collect()
     }
+
+    fn tiled_in_group(&self, group_id: GroupId) -> Vec<WindowId> {
+        self.iter()
+            .filter(|w| w.group == group_id && w.floating.is_none() && !w.fullscreen)
+            .map(|w| w.id)
+            .collect()
+    }
 }
 
-pub struct Lanta {
-    connection: Rc<Connection>,
-    keys: KeyHandlers,
+pub struct Lanta<C = Connection> {
+    connection: Rc<C>,
+    keys: KeyHandlers<C>,
     groups: Vec<Group>,
     windows: Vec<Window>,
     layouts: Vec<Box<dyn Layout<WindowId>>>,
+    rules: Vec<Rule>,
     crtc: HashMap<Crtc, (CrtcInfo, GroupId)>,
     screen: Screen<Dock>,
     current_crtc: Option<Crtc>,
     children: Vec<Child>,
     mapped: Vec<MappedWindow<WindowId>>,
+    ipc: Option<Ipc<C>>,
+    reload: Option<Reload<C>>,
+    close_timeout: Option<Duration>,
+    group_policy: GroupPolicy,
+    back_and_forth: bool,
+    /// The groups each screen showed before its current one, the most recent
+    /// last.
+    group_history: HashMap<Crtc, Vec<GroupId>>,
+    closing: Vec<Closing>,
+    timer: timer::Timer,
+    /// The windows that want attention, the most recent last.
+    urgent: Vec<WindowId>,
+    /// The focused window that subscribers were last told about.
+    notified_focus: Option<WindowId>,
 }
 
 impl Lanta {
-    pub fn new<K, G>(keys: K, groups: G, layouts: Vec<Box<dyn Layout<WindowId>>>) -> Result<Self>
+    pub fn new<K, G>(
+        keys: K,
+        groups: G,
+        layouts: Vec<Box<dyn Layout<WindowId>>>,
+        rules: Vec<Rule>,
+    ) -> Result<Self>
     where
         K: Into<KeyHandlers>,
         G: IntoIterator<Item = Group>,
+    {
+        let saved = restart::take_saved_state();
+        Self::with_connection(Connection::connect()?, keys, groups, layouts, rules, saved)
+    }
+}
+
+impl<C: XConnection> Lanta<C> {
+    /// Creates a window manager that talks to the X server through `connection`.
+    ///
+    /// Existing windows are put back where `saved` says they were, if given.
+    pub fn with_connection<K, G>(
+        connection: C,
+        keys: K,
+        groups: G,
+        layouts: Vec<Box<dyn Layout<WindowId>>>,
+        rules: Vec<Rule>,
+        saved: Option<SavedState>,
+    ) -> Result<Self>
+    where
+        K: Into<KeyHandlers<C>>,
+        G: IntoIterator<Item = Group>,
     {
         let keys = keys.into();
-        let connection = Rc::new(Connection::connect()?);
-        connection.install_as_wm(&keys)?;
+        let connection = Rc::new(connection);
+        connection.install_as_wm(&keys.key_combos())?;
 
         let groups = groups.into_iter().collect::<Vec<Group>>().into();
         let mut crtc = connection.list_crtc()?;
@@ -94,18 +233,34 @@ impl Lanta {
             groups,
             windows: Default::default(),
             layouts: layouts,
+            rules,
             connection: connection,
             screen: Screen::default(),
             crtc,
             children: Vec::new(),
             current_crtc,
             mapped: Vec::new(),
+            ipc: None,
+            reload: None,
+            close_timeout: None,
+            group_policy: GroupPolicy::default(),
+            back_and_forth: false,
+            group_history: HashMap::new(),
+            closing: Vec::new(),
+            timer: timer::Timer::new()?,
+            urgent: Vec::new(),
+            notified_focus: None,
         };
 
         // Learn about existing top-level windows.
         let existing_windows = wm.connection.top_level_windows()?;
-        for window in existing_windows {
-            wm.manage_window(window);
+        match saved {
+            Some(saved) => wm.restore(saved, existing_windows),
+            None => {
+                for window in existing_windows {
+                    wm.adopt_window(window);
+                }
+            }
         }
         wm.activate_current_groups();
         wm.update_ewmh_desktops();
@@ -113,8 +268,303 @@ impl Lanta {
         Ok(wm)
     }
 
+    /// Places `existing` windows according to the state saved before a restart.
+    ///
+    /// Windows that weren't managed before the restart are added to the
+    /// active group as usual.
+    fn restore(&mut self, saved: SavedState, existing: Vec<WindowId>) {
+        // Keep the groups that were added, renamed, removed or reordered at
+        // runtime. Configured groups that weren't saved, such as ones just
+        // added to the config file, follow them.
+        let names: Option<Vec<String>> = saved.groups.iter().map(|g| g.name.clone()).collect();
+        if let Some(names) = names.filter(|n| n.len() >= cmp::max(self.crtc.len(), 1)) {
+            let mut configured = std::mem::take(&mut self.groups);
+            self.groups = names
+                .into_iter()
+                .map(
+                    |name| match configured.iter().position(|g| g.name() == name) {
+                        Some(pos) => configured.remove(pos),
+                        None => Group::new(name, "", &self.layouts),
+                    },
+                )
+                .collect();
+            self.groups.extend(configured);
+            self.assign_crtc_groups(|_crtc, gid| gid);
+        }
+        let num_groups = self.groups.len();
+        let saved_crtc: HashMap<_, _> = saved.crtc.into_iter().collect();
+        self.assign_crtc_groups(|crtc, gid| match saved_crtc.get(&crtc) {
+            Some(&saved_gid) if saved_gid < num_groups => saved_gid,
+            _ => gid,
+        });
+        if let Some(crtc) = saved.current_crtc.filter(|c| self.crtc.contains_key(c)) {
+            self.current_crtc = Some(crtc);
+        }
+
+        for window in &saved.windows {
+            if existing.contains(&window.id) && window.group < num_groups {
+                self.manage_window_in(window.id, Some(window.group));
+                if let Some(w) = self.windows.iter_mut().find(|w| w.id == window.id) {
+                    w.floating = window.floating;
+                }
+            }
+        }
+        for window in existing {
+            if !saved.windows.iter().any(|w| w.id == window) {
+                self.adopt_window(window);
+            }
+        }
+
+        let num_layouts = self.layouts.len();
+        for (gid, (group, saved)) in self.groups.iter_mut().zip(saved.groups).enumerate() {
+            if saved.layout_id < num_layouts {
+                group.layout_id = saved.layout_id;
+            }
+            if let Some(focused) = saved.focused_window {
+                if self
+                    .windows
+                    .iter()
+                    .any(|w| w.id == focused && w.group == gid)
+                {
+                    group.focused_window = Some(focused);
+                }
+            }
+        }
+    }
+
+    /// Manages a window that existed before Lanta started, putting it back in
+    /// the group named by its `_NET_WM_DESKTOP`.
+    ///
+    /// Only windows that are shown, or that were managed by the previous
+    /// window manager, are adopted. Others have never been mapped by their
+    /// client, or were withdrawn.
+    fn adopt_window(&mut self, window_id: WindowId) {
+        let managed = match self.connection.get_wm_state(&window_id) {
+            Some(WmState::Normal) | Some(WmState::Iconic) => true,
+            Some(WmState::Withdrawn) | None => false,
+        };
+        if !managed && !self.connection.is_viewable(&window_id) {
+            debug!("Not adopting unmapped window {}", window_id);
+            return;
+        }
+        let group = self
+            .connection
+            .get_window_desktop(&window_id)
+            .filter(|&gid| gid < self.groups.len());
+        self.manage_window_in(window_id, group);
+    }
+
+    /// Describes the placement of every window, to be restored by the
+    /// restarted window manager.
+    pub fn saved_state(&self) -> SavedState {
+        SavedState {
+            windows: self
+                .windows
+                .iter()
+                .map(|w| restart::SavedWindow {
+                    id: w.id,
+                    group: w.group,
+                    floating: w.floating,
+                })
+                .collect(),
+            groups: self
+                .groups
+                .iter()
+                .map(|g| restart::SavedGroup {
+                    name: Some(g.name().to_owned()),
+                    layout_id: g.layout_id,
+                    focused_window: g.focused_window,
+                })
+                .collect(),
+            crtc: self
+                .crtc
+                .iter()
+                .map(|(&crtc, &(_, gid))| (crtc, gid))
+                .collect(),
+            current_crtc: self.current_crtc,
+        }
+    }
+
+    /// Replaces the running window manager with a fresh copy of its
+    /// executable, keeping every window where it is.
+    ///
+    /// Only returns if the restart failed.
+    pub fn restart(&mut self) -> Result<()> {
+        let state = self.saved_state();
+        // The control socket is left in place: its file descriptor is closed
+        // on exec, and the new process binds the same path again.
+        restart::exec_with_state(&state)
+    }
+
+    /// Reassigns the group shown on each screen with `group_of`, making sure
+    /// that no two screens show the same group.
+    fn assign_crtc_groups(&mut self, mut group_of: impl FnMut(Crtc, GroupId) -> GroupId) {
+        let num_groups = self.groups.len();
+        let mut allocated = HashSet::new();
+        for (crtc, (_info, gid)) in self.crtc.iter_mut() {
+            *gid = group_of(*crtc, *gid);
+            if !allocated.insert(*gid) {
+                *gid = (0..num_groups)
+                    .find(|g| !allocated.contains(g))
+                    .expect("There is a group for each screen");
+                allocated.insert(*gid);
+            }
+        }
+    }
+
+    /// Moves every window, screen and history entry from the group at each
+    /// old position to the group at `new_gids[old]`, after `self.groups` was
+    /// changed.
+    fn renumber_groups(&mut self, new_gids: &[GroupId]) {
+        for w in &mut self.windows {
+            w.group = new_gids[w.group];
+            self.connection.set_window_desktop(&w.id, w.group);
+        }
+        for history in self.group_history.values_mut() {
+            let mut renumbered: Vec<GroupId> = Vec::new();
+            for gid in history.iter().map(|&gid| new_gids[gid]) {
+                renumbered.retain(|&g| g != gid);
+                renumbered.push(gid);
+            }
+            *history = renumbered;
+        }
+        self.assign_crtc_groups(|_crtc, gid| new_gids[gid]);
+        for gid in 0..self.groups.len() {
+            let windows = self.windows.in_group(gid);
+            let group = &mut self.groups[gid];
+            if group
+                .focused_window
+                .filter(|w| windows.contains(w))
+                .is_none()
+            {
+                group.focused_window = windows.first().cloned();
+            }
+        }
+    }
+
+    /// Accepts commands from other programs on a Unix socket at `path`.
+    ///
+    /// Each command is turned into a `Command` by `parse` and run as if it
+    /// had been bound to a key.
+    pub fn listen<P, F>(&mut self, path: P, parse: F) -> Result<()>
+    where
+        P: Into<PathBuf>,
+        F: Fn(&str) -> Result<Command<C>> + 'static,
+    {
+        self.ipc = Some(Ipc {
+            server: ipc::Server::bind(path)?,
+            parse: Box::new(parse),
+            subscribers: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Allows the configuration to be replaced while running, either by
+    /// `reload()` or by sending SIGHUP to the process.
+    pub fn reload_with<F>(&mut self, load: F) -> Result<()>
+    where
+        F: Fn() -> Result<Config<C>> + 'static,
+    {
+        self.reload = Some(Reload {
+            load: Box::new(load),
+            hangup: signal::Hangup::install()?,
+        });
+        Ok(())
+    }
+
+    /// Reads the configuration again and applies it.
+    ///
+    /// If the configuration can't be read, the current one is left in place.
+    pub fn reload(&mut self) -> Result<()> {
+        let config = match self.reload {
+            Some(ref reload) => (reload.load)()?,
+            None => return Err("No way to reload the configuration was given".into()),
+        };
+        self.reconfigure(config)
+    }
+
+    /// Replaces the key bindings, layouts, groups and window rules.
+    ///
+    /// Groups are matched up with the old ones by name, so that windows stay
+    /// with their group. Windows in a group that no longer exists are moved
+    /// to the group at the same position, or the last group.
+    pub fn reconfigure(&mut self, config: Config<C>) -> Result<()> {
+        let Config {
+            keys,
+            mut groups,
+            layouts,
+            rules,
+            close_timeout,
+            group_policy,
+            back_and_forth,
+        } = config;
+        if layouts.is_empty() {
+            return Err("The configuration must contain at least one layout".into());
+        }
+        if groups.len() < cmp::max(self.crtc.len(), 1) {
+            return Err(format!(
+                "The configuration must contain a group for each of the {} screens",
+                self.crtc.len()
+            )
+            .into());
+        }
+
+        let root = *self.connection.root_window_id();
+        let grabbed: Vec<WindowId> = std::iter::once(root)
+            .chain(self.windows.iter().map(|w| w.id))
+            .chain(self.screen.docks().iter().map(|d| d.window_id()))
+            .collect();
+        for window_id in &grabbed {
+            self.connection.disable_window_key_events(window_id);
+            self.connection
+                .enable_window_key_events(window_id, &keys.key_combos());
+        }
+        self.keys = keys;
+
+        let new_gids: Vec<GroupId> = self
+            .groups
+            .iter()
+            .enumerate()
+            .map(|(old_gid, old)| {
+                groups
+                    .iter()
+                    .position(|g| g.name() == old.name())
+                    .unwrap_or_else(|| cmp::min(old_gid, groups.len() - 1))
+            })
+            .collect();
+        for group in groups.iter_mut() {
+            if let Some(old) = self.groups.iter().find(|g| g.name() == group.name()) {
+                // Keep the layout chosen at runtime, if it still exists and
+                // the group still cycles through it.
+                if let Some(layout_id) = self
+                    .layouts
+                    .get(old.layout_id)
+                    .and_then(|old| layouts.iter().position(|l| l.name() == old.name()))
+                    .filter(|id| group.layout_cycle.is_empty() || group.layout_cycle.contains(id))
+                {
+                    group.layout_id = layout_id;
+                }
+                group.focused_window = old.focused_window;
+                group.last_crtc = old.last_crtc;
+            }
+        }
+        self.groups = groups;
+        self.layouts = layouts;
+        self.rules = rules;
+        self.close_timeout = close_timeout;
+        self.group_policy = group_policy;
+        self.back_and_forth = back_and_forth;
+        self.renumber_groups(&new_gids);
+
+        self.activate_current_groups();
+        self.update_ewmh_desktops();
+        self.notify(Notification::Reconfigured);
+        info!("Configuration reloaded");
+        Ok(())
+    }
+
     fn groupref(&self, group_id: GroupId) -> (Stack<WindowId>, &dyn Layout<WindowId>) {
-        let windows = self.windows.in_group(group_id);
+        let windows = self.windows.tiled_in_group(group_id);
         let group = self
             .groups
             .get(group_id)
@@ -132,12 +582,45 @@ impl Lanta {
     }
 
     fn activate_current_groups(&mut self) {
+        self.focus_modal_dialogs();
+        for (&crtc, &(_, gid)) in self.crtc.iter() {
+            if let Some(group) = self.groups.get_mut(gid) {
+                group.last_crtc = Some(crtc);
+            }
+        }
         let vps = self.viewports();
         let Lanta { ref crtc, .. } = self;
         let mut new_mapped_windows = Vec::new();
-        for ((_crtc_id, (_info, grp_id)), viewport) in crtc.iter().zip(vps.into_iter()) {
+        let mut floating = Vec::new();
+        let mut fullscreen = Vec::new();
+        for ((_crtc_id, (info, grp_id)), viewport) in crtc.iter().zip(vps.into_iter()) {
             let (windows, layout) = self.groupref(*grp_id);
-            new_mapped_windows.extend(layout.layout(&viewport, &windows).into_iter());
+            let tiled = layout.layout(&viewport, &windows).into_iter().map(|w| {
+                let window = self.windows.iter().find(|x| x.id == w.id);
+                let vp = window.map_or(w.vp, |x| x.size_hints.fit(&w.vp));
+                MappedWindow { id: w.id, vp }
+            });
+            new_mapped_windows.extend(tiled);
+            for w in self.windows.iter().filter(|w| w.group == *grp_id) {
+                if w.fullscreen {
+                    // Docks are covered too.
+                    fullscreen.push(w.id);
+                    new_mapped_windows.push(MappedWindow {
+                        id: w.id,
+                        vp: Viewport::clone_from_crtc_info(info),
+                    });
+                } else if let Some(vp) = w.floating {
+                    floating.push(w.id);
+                    new_mapped_windows.push(MappedWindow {
+                        id: w.id,
+                        vp: Viewport {
+                            x: viewport.x + vp.x,
+                            y: viewport.y + vp.y,
+                            ..vp
+                        },
+                    });
+                }
+            }
         }
 
         let prev_ids: HashSet<_> = self.mapped.iter().map(|w| w.id).collect();
@@ -149,6 +632,10 @@ impl Lanta {
             self.connection.disable_window_tracking(id);
             self.connection.unmap_window(id);
             self.connection.enable_window_tracking(id);
+            // Windows in hidden groups are still managed, just not shown.
+            if self.is_window_managed(id) {
+                self.connection.set_wm_state(id, WmState::Iconic);
+            }
         }
         for MappedWindow { id, vp } in next.difference(&prev) {
             self.connection.disable_window_tracking(id);
@@ -160,15 +647,118 @@ impl Lanta {
             self.connection.disable_window_tracking(id);
             self.connection.map_window(id);
             self.connection.enable_window_tracking(id);
+            self.connection.set_wm_state(id, WmState::Normal);
+        }
+        // Tiled windows that were just mapped end up on top of the stack.
+        for id in floating.iter().chain(fullscreen.iter()) {
+            self.connection.raise_window(id);
         }
         self.mapped = new_mapped_windows;
-        self.connection.focus(
-            self.current_crtc
-                .as_ref()
-                .and_then(|c| self.crtc.get(c))
-                .and_then(|(_info, gid)| self.groups.get(*gid))
-                .and_then(|grp| grp.focused_window.as_ref()),
-        )
+        let focused = self
+            .current_crtc
+            .as_ref()
+            .and_then(|c| self.crtc.get(c))
+            .and_then(|(_info, gid)| self.groups.get(*gid))
+            .and_then(|grp| grp.focused_window);
+        if let Some(id) = focused {
+            // The user has seen the window now.
+            self.set_urgency(&id, Some(false), Some(false));
+        }
+        self.give_input_focus(focused.as_ref());
+        if focused != self.notified_focus {
+            self.notified_focus = focused;
+            if let Some(id) = focused {
+                self.notify(Notification::WindowFocused { id });
+            }
+        }
+    }
+
+    /// Changes where a window's urgency comes from, leaving alone whatever is
+    /// `None`, and keeps track of the windows that want attention.
+    fn set_urgency(&mut self, window_id: &WindowId, hint: Option<bool>, attention: Option<bool>) {
+        let window = self.windows.iter_mut().find(|w| &w.id == window_id);
+        if let Some(window) = window {
+            window.urgent_hint = hint.unwrap_or(window.urgent_hint);
+            window.demands_attention = attention.unwrap_or(window.demands_attention);
+        }
+        self.update_urgency(window_id);
+    }
+
+    fn update_urgency(&mut self, window_id: &WindowId) {
+        let urgent = match self.windows.iter().find(|w| &w.id == window_id) {
+            Some(w) => w.urgent_hint || w.demands_attention,
+            None => return,
+        };
+        if urgent == self.urgent.contains(window_id) {
+            return;
+        }
+        if urgent {
+            self.urgent.push(*window_id);
+        } else {
+            self.urgent.retain(|w| w != window_id);
+        }
+        // Pagers learn about urgency from the EWMH state alone.
+        self.set_window_state(window_id, WindowState::DemandsAttention, urgent);
+        self.notify(Notification::WindowUrgency {
+            id: *window_id,
+            urgent,
+        });
+    }
+
+    /// Focuses the window that most recently asked for attention, showing its
+    /// group if it is hidden.
+    pub fn focus_urgent(&mut self) {
+        if let Some(&id) = self.urgent.last() {
+            self.show_window(&id);
+        }
+    }
+
+    /// Moves the focus in each group from a window to its modal dialog, which
+    /// has to be dismissed first.
+    fn focus_modal_dialogs(&mut self) {
+        let Lanta {
+            ref mut groups,
+            ref windows,
+            ..
+        } = self;
+        for (gid, group) in groups.iter_mut().enumerate() {
+            // Dialogs can have dialogs of their own.
+            for _ in 0..windows.len() {
+                let focused = group.focused_window;
+                let modal = windows.iter().find(|w| {
+                    w.modal
+                        && w.group == gid
+                        && w.transient_for.is_some()
+                        && w.transient_for == focused
+                });
+                match modal {
+                    Some(modal) => group.focused_window = Some(modal.id),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Focuses a window in whichever way it expects, as ICCCM describes.
+    fn give_input_focus(&self, window: Option<&WindowId>) {
+        let window_id = match window {
+            Some(window_id) => window_id,
+            None => return self.connection.focus(None),
+        };
+        let model = self.connection.get_input_model(window_id);
+        if model.accepts_input() {
+            self.connection.focus(Some(window_id));
+        } else {
+            if !model.takes_focus() {
+                // Keys must not go to the window that was focused before,
+                // which may no longer be shown.
+                self.connection.focus(None);
+            }
+            self.connection.set_active_window(window_id);
+        }
+        if model.takes_focus() {
+            self.connection.send_take_focus(window_id);
+        }
     }
 
     fn find_next_unallocated_group(&self) -> GroupId {
@@ -189,7 +779,33 @@ impl Lanta {
             &self.groups,
             current_group,
             self.windows.iter().map(|w| &w.id).collect(),
-        )
+        );
+        // Which screen each group is on may have changed.
+        self.update_ewmh_geometry();
+    }
+
+    /// Publishes the size of the screen and, for each group, the area of the
+    /// screen showing it that is not covered by docks.
+    fn update_ewmh_geometry(&self) {
+        let full: Vec<_> = self
+            .crtc
+            .values()
+            .map(|(info, _)| Viewport::clone_from_crtc_info(info))
+            .collect();
+        let width = full.iter().map(|v| v.x + v.width).fold(0, cmp::max);
+        let height = full.iter().map(|v| v.y + v.height).fold(0, cmp::max);
+        let workarea: Vec<_> = (0..self.groups.len())
+            .map(|gid| {
+                self.group_viewport(gid).unwrap_or(Viewport {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                })
+            })
+            .collect();
+        self.connection
+            .update_ewmh_geometry(width, height, &workarea);
     }
 
     fn group_idx(&self) -> Option<usize> {
@@ -225,33 +841,143 @@ impl Lanta {
         self.children.push(cld);
     }
     pub fn group_cycle_layouts(&mut self) {
+        self.step_group_layout(true);
+    }
+
+    /// Cycles the current group back to its previous layout.
+    pub fn group_prev_layout(&mut self) {
+        self.step_group_layout(false);
+    }
+
+    fn step_group_layout(&mut self, forward: bool) {
         let num_layouts = self.layouts.len();
-        if let Some(group) = self.group_idx().and_then(|gid| self.groups.get_mut(gid)) {
-            if let Some(next_layout) = (group.layout_id + 1).checked_rem(num_layouts) {
-                group.layout_id = next_layout;
+        if let Some(gid) = self.group_idx() {
+            if let Some(layout_id) = self.groups[gid].step_layout(num_layouts, forward) {
+                self.set_group_layout(gid, layout_id);
             }
         }
         self.activate_current_groups()
     }
 
+    /// Switches the current group to the named layout, even one that it
+    /// doesn't cycle through.
+    pub fn set_layout(&mut self, name: &str) -> Result<()> {
+        let layout_id = self
+            .layouts
+            .iter()
+            .position(|l| l.name() == name)
+            .ok_or_else(|| format!("No such layout: {}", name))?;
+        if let Some(gid) = self.group_idx() {
+            if self.groups[gid].layout_id != layout_id {
+                self.set_group_layout(gid, layout_id);
+            }
+        }
+        self.activate_current_groups();
+        Ok(())
+    }
+
+    fn set_group_layout(&mut self, gid: GroupId, layout_id: usize) {
+        self.groups[gid].layout_id = layout_id;
+        let layout = self.layouts[layout_id].name().to_owned();
+        self.notify(Notification::LayoutChanged { group: gid, layout });
+    }
+
     pub fn close_focused(&mut self) {
-        if let Some(id) = self
-            .group_idx()
-            .and_then(|gid| self.groups.get(gid))
-            .and_then(|g| g.focused_window)
-        {
-            self.connection.close_window(&id)
+        if let Some(id) = self.focused_window() {
+            self.close_window(id)
         }
     }
 
-    fn add_window_to_active_group(&mut self, id: WindowId) {
-        if let Some(gid) = self.group_idx() {
-            self.add_window_to_group(id, gid)
+    /// Kills the client of the focused window, without asking it to close.
+    pub fn kill_focused(&mut self) {
+        if let Some(id) = self.focused_window() {
+            self.connection.kill_window(&id)
+        }
+    }
+
+    /// Sets how long a window has to close before its client is killed, if
+    /// it stops responding.
+    pub fn set_close_timeout(&mut self, timeout: Option<Duration>) {
+        self.close_timeout = timeout;
+    }
+
+    /// Sets what happens when switching to a group shown on another screen.
+    pub fn set_group_policy(&mut self, policy: GroupPolicy) {
+        self.group_policy = policy;
+    }
+
+    /// Sets whether going to the group that is already shown goes back to
+    /// the previous one instead.
+    pub fn set_back_and_forth(&mut self, enabled: bool) {
+        self.back_and_forth = enabled;
+    }
+
+    fn close_window(&mut self, id: WindowId) {
+        self.connection.close_window(&id);
+        let timeout = match self.close_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        // Clients that can't be pinged get no benefit of the doubt.
+        self.connection.ping(&id);
+        self.closing.retain(|c| c.id != id);
+        self.closing.push(Closing {
+            id,
+            deadline: Instant::now() + timeout,
+            responsive: false,
+        });
+        self.set_timer();
+    }
+
+    /// Arms the timer for the next window that may need killing.
+    fn set_timer(&self) {
+        let now = Instant::now();
+        let next = self.closing.iter().map(|c| c.deadline).min();
+        self.timer
+            .set(next.map(|d| d.saturating_duration_since(now)));
+    }
+
+    fn on_timer(&mut self) {
+        self.timer.drain();
+        let now = Instant::now();
+        let (expired, waiting) = self.closing.drain(..).partition(|c| c.deadline <= now);
+        self.closing = waiting;
+        for closing in expired {
+            if !self.is_window_managed(&closing.id) {
+                continue;
+            }
+            if closing.responsive {
+                info!("Window {} is still open, but responding", closing.id);
+            } else {
+                warn!("Window {} did not close in time", closing.id);
+                self.connection.kill_window(&closing.id);
+            }
         }
+        self.set_timer();
     }
 
-    fn add_window_to_group(&mut self, id: WindowId, group: GroupId) {
-        self.windows.push(Window { id, group });
+    fn on_ping_reply(&mut self, window_id: &WindowId) {
+        for closing in self.closing.iter_mut().filter(|c| &c.id == window_id) {
+            closing.responsive = true;
+        }
+    }
+
+    fn add_window_to_group(&mut self, id: WindowId, group: GroupId, floating: Option<Viewport>) {
+        let states = self.connection.get_window_states(&id);
+        self.windows.push(Window {
+            id,
+            group,
+            floating,
+            fullscreen: false,
+            size_hints: self.connection.get_size_hints(&id),
+            transient_for: self.connection.get_transient_for(&id),
+            modal: states.contains(&WindowState::Modal),
+            urgent_hint: self.connection.is_urgent(&id),
+            demands_attention: states.contains(&WindowState::DemandsAttention),
+        });
+        self.connection.set_window_desktop(&id, group);
+        self.notify(Notification::WindowManaged { id, group });
+        self.update_urgency(&id);
         let group = self
             .groups
             .get_mut(group)
@@ -268,15 +994,19 @@ impl Lanta {
             if let Some(group) = self.groups.get_mut(window.group) {
                 if Some(window.id) == group.focused_window {
                     let windows = self.windows.in_group(window.group);
-                    group.focused_window = windows
-                        .iter()
-                        .position(|w| w == id)
-                        .and_then(|p| {
-                            p.checked_sub(1)
-                                .and_then(|p| windows.get(p))
-                                .or_else(|| windows.get(p + 1))
-                        })
-                        .map(|&w| w);
+                    // A closed dialog hands the focus back to its parent.
+                    let parent = window.transient_for.filter(|p| windows.contains(p));
+                    group.focused_window = parent.or_else(|| {
+                        windows
+                            .iter()
+                            .position(|w| w == id)
+                            .and_then(|p| {
+                                p.checked_sub(1)
+                                    .and_then(|p| windows.get(p))
+                                    .or_else(|| windows.get(p + 1))
+                            })
+                            .map(|&w| w)
+                    });
                 }
             } else {
                 error!(
@@ -287,7 +1017,12 @@ impl Lanta {
         } else {
             error!("Could not lookup window {:?} to remove", id);
         }
-        self.windows.retain(|w| &w.id != id);
+        self.closing.retain(|c| &c.id != id);
+        self.urgent.retain(|w| w != id);
+        if self.is_window_managed(id) {
+            self.windows.retain(|w| &w.id != id);
+            self.notify(Notification::WindowUnmanaged { id: *id });
+        }
         self.activate_current_groups();
         self.update_ewmh_desktops();
     }
@@ -347,8 +1082,15 @@ impl Lanta {
         let rhs_pos = self.windows.iter().position(|w| w.id == rhs);
         match (lhs_pos, rhs_pos) {
             (Some(lhs_pos), Some(rhs_pos)) => {
-                self.windows.get_mut(lhs_pos).unwrap().id = rhs;
-                self.windows.get_mut(rhs_pos).unwrap().id = lhs;
+                // The windows trade places and groups, but keep their own
+                // floating and fullscreen state.
+                let lhs_group = self.windows[lhs_pos].group;
+                let rhs_group = self.windows[rhs_pos].group;
+                self.windows.swap(lhs_pos, rhs_pos);
+                self.windows[lhs_pos].group = lhs_group;
+                self.windows[rhs_pos].group = rhs_group;
+                self.connection.set_window_desktop(&rhs, lhs_group);
+                self.connection.set_window_desktop(&lhs, rhs_group);
                 self.activate_current_groups();
             }
             (Some(_), None) => {
@@ -386,9 +1128,21 @@ impl Lanta {
     }
 
     fn move_window_to_group(&mut self, id: WindowId, group: GroupId) {
-        for w in &mut self.windows {
-            if w.id == id {
-                w.group = group
+        let old_group = match self.windows.iter_mut().find(|w| w.id == id) {
+            Some(w) => std::mem::replace(&mut w.group, group),
+            None => return,
+        };
+        self.connection.set_window_desktop(&id, group);
+        // The window can no longer be focused in the group it left.
+        let remaining = self.windows.in_group(old_group);
+        if let Some(old) = self.groups.get_mut(old_group) {
+            if old.focused_window == Some(id) {
+                old.focused_window = remaining.first().cloned();
+            }
+        }
+        if let Some(new) = self.groups.get_mut(group) {
+            if new.focused_window.is_none() {
+                new.focused_window = Some(id);
             }
         }
     }
@@ -397,25 +1151,64 @@ impl Lanta {
         if new_idx >= self.groups.len() {
             return;
         }
-        let after_insert = self
-            .current_crtc
-            .as_ref()
-            .and_then(|c| self.crtc.get(c))
-            .map(|(_info, gidx)| gidx.clone());
-        match after_insert {
-            Some(old_idx) if old_idx != new_idx => {
-                for (_info, ref mut gid) in self.crtc.values_mut() {
-                    if *gid == new_idx {
-                        *gid = old_idx;
+        let before = (self.current_crtc, self.group_idx());
+        let shown: Vec<(Crtc, GroupId)> = self
+            .crtc
+            .iter()
+            .map(|(&crtc, &(_, gid))| (crtc, gid))
+            .collect();
+        let shown_on = self
+            .crtc
+            .iter()
+            .find(|(_crtc, (_info, gid))| *gid == new_idx)
+            .map(|(&crtc, _)| crtc);
+        match (self.group_policy, shown_on) {
+            (GroupPolicy::View, Some(crtc)) | (GroupPolicy::Pinned, Some(crtc)) => {
+                self.current_crtc = Some(crtc);
+            }
+            (GroupPolicy::Pinned, None) => {
+                let home = self.groups[new_idx]
+                    .last_crtc
+                    .filter(|crtc| self.crtc.contains_key(crtc));
+                if home.is_some() {
+                    self.current_crtc = home;
+                }
+                self.show_group(new_idx);
+            }
+            (_, shown_on) => {
+                // Swap with whichever screen is showing the group.
+                if let Some(old_idx) = self.group_idx().filter(|_| shown_on.is_some()) {
+                    for (_info, ref mut gid) in self.crtc.values_mut() {
+                        if *gid == new_idx {
+                            *gid = old_idx;
+                        }
                     }
                 }
+                self.show_group(new_idx);
+            }
+        }
+        for (crtc, old_gid) in shown {
+            if self.crtc.get(&crtc).is_some_and(|&(_, gid)| gid != old_gid) {
+                let history = self.group_history.entry(crtc).or_default();
+                history.retain(|&gid| gid != old_gid);
+                history.push(old_gid);
             }
-            Some(_) | None => (),
-        };
-        if let Some((_info, idx)) = self.current_crtc.and_then(|c| self.crtc.get_mut(&c)) {
-            *idx = new_idx;
         }
         self.update_ewmh_desktops();
+        if before != (self.current_crtc, Some(new_idx)) {
+            self.notify(Notification::GroupFocused {
+                crtc: self.current_crtc,
+                group: new_idx,
+            });
+        }
+    }
+
+    /// Shows a group on the current screen, which must not be showing it
+    /// elsewhere.
+    fn show_group(&mut self, gid: GroupId) {
+        if let Some((_info, idx)) = self.current_crtc.and_then(|c| self.crtc.get_mut(&c)) {
+            *idx = gid;
+        }
     }
 
     fn shift_group(&mut self, fun: impl FnOnce(usize, usize) -> Option<usize>) {
@@ -433,55 +1226,212 @@ impl Lanta {
         self.shift_group(|cur, _len| cur.checked_sub(1))
     }
 
-    fn move_focused_to_group(&mut self, fun: impl FnOnce(usize, usize) -> Option<usize>) {
-        if let Some(gid) = self.group_idx().and_then(|idx| fun(idx, self.groups.len())) {
-            if let Some(id) = self.focused_window() {
-                self.move_window_to_group(id, gid);
-            }
+    /// Shows the selected group on the current screen.
+    ///
+    /// If the group is already shown there and back-and-forth is enabled, the
+    /// previous group is shown instead.
+    pub fn go_to_group(&mut self, group: &GroupSelector) -> Result<()> {
+        let gid = self.find_group(group)?;
+        if self.back_and_forth && self.group_idx() == Some(gid) {
+            self.group_last();
+            return Ok(());
+        }
+        self.focus_group(gid);
+        self.activate_current_groups();
+        Ok(())
+    }
+
+    /// Shows the group that the current screen showed before its current one.
+    pub fn group_last(&mut self) {
+        let current = self.group_idx();
+        let last = self
+            .current_crtc
+            .and_then(|crtc| self.group_history.get(&crtc))
+            .and_then(|history| {
+                history
+                    .iter()
+                    .rev()
+                    .find(|&&gid| Some(gid) != current && gid < self.groups.len())
+            })
+            .cloned();
+        if let Some(gid) = last {
             self.focus_group(gid);
             self.activate_current_groups();
         }
     }
 
-    pub fn move_focused_to_next_group(&mut self) {
-        self.move_focused_to_group(|cur, len| if cur + 1 < len { Some(cur + 1) } else { None });
+    /// Adds a group at the end, with the named layout or else the first one.
+    pub fn add_group(&mut self, name: &str, layout: Option<&str>) -> Result<()> {
+        self.check_group_name(name)?;
+        let layout = match layout {
+            Some(layout) if self.layouts.iter().all(|l| l.name() != layout) => {
+                return Err(format!("No such layout: {}", layout).into());
+            }
+            Some(layout) => layout,
+            None => self.layouts[0].name(),
+        };
+        let group = Group::new(name.to_owned(), layout, &self.layouts);
+        self.groups.push(group);
+        self.groups_changed();
+        Ok(())
     }
 
-    pub fn move_focused_to_prev_group(&mut self) {
-        self.move_focused_to_group(|idx, _| idx.checked_sub(1));
+    pub fn rename_group(&mut self, group: &GroupSelector, name: &str) -> Result<()> {
+        let gid = self.find_group(group)?;
+        if self.groups[gid].name() != name {
+            self.check_group_name(name)?;
+            self.groups[gid].set_name(name.to_owned());
+            self.groups_changed();
+        }
+        Ok(())
     }
 
-    fn is_window_managed(&self, window_id: &WindowId) -> bool {
-        self.windows.iter().find(|w| &w.id == window_id).is_some()
+    /// Removes a group, moving its windows to the group before it, or the
+    /// one after if it is the first.
+    pub fn remove_group(&mut self, group: &GroupSelector) -> Result<()> {
+        let gid = self.find_group(group)?;
+        if self.groups.len() <= cmp::max(self.crtc.len(), 1) {
+            return Err("Every screen must have a group to show".into());
+        }
+        let neighbor = if gid > 0 { gid - 1 } else { 1 };
+        if self.groups[neighbor].focused_window.is_none() {
+            self.groups[neighbor].focused_window = self.groups[gid].focused_window;
+        }
+        // A screen showing the group shows its neighbor instead, unless
+        // another screen already does.
+        let replacement = if self.crtc.values().any(|&(_, g)| g == neighbor) {
+            self.find_next_unallocated_group()
+        } else {
+            neighbor
+        };
+        for (_info, ref mut g) in self.crtc.values_mut() {
+            if *g == gid {
+                *g = replacement;
+            }
+        }
+        for history in self.group_history.values_mut() {
+            history.retain(|&g| g != gid);
+        }
+        self.groups.remove(gid);
+        let new_gids: Vec<GroupId> = (0..=self.groups.len())
+            .map(|g| if g == gid { neighbor } else { g })
+            .map(|g| if g > gid { g - 1 } else { g })
+            .collect();
+        self.renumber_groups(&new_gids);
+        self.groups_changed();
+        Ok(())
     }
 
-    pub fn manage_window(&mut self, window_id: WindowId) {
-        debug!("Managing window: {}", window_id);
+    /// Moves a group to position `index`, shifting the groups in between.
+    pub fn reorder_group(&mut self, group: &GroupSelector, index: usize) -> Result<()> {
+        let gid = self.find_group(group)?;
+        if index >= self.groups.len() {
+            return Err(format!("No group position {}", index).into());
+        }
+        let mut order: Vec<GroupId> = (0..self.groups.len()).collect();
+        order.remove(gid);
+        order.insert(index, gid);
+        let group = self.groups.remove(gid);
+        self.groups.insert(index, group);
+        let mut new_gids = vec![0; order.len()];
+        for (new_gid, &old_gid) in order.iter().enumerate() {
+            new_gids[old_gid] = new_gid;
+        }
+        self.renumber_groups(&new_gids);
+        self.groups_changed();
+        Ok(())
+    }
 
-        // If we are already managing the window, then do nothing. We do not
-        // want the window to end up in two groups at once. We shouldn't
-        // be called in such cases, so treat it as an error.
-        if self.is_window_managed(&window_id) {
-            error!(
-                "Asked to manage window that's already managed: {}",
-                window_id
-            );
-            return;
+    fn check_group_name(&self, name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Err("A group needs a name".into());
+        }
+        if self.groups.iter().any(|g| g.name() == name) {
+            return Err(format!("There is already a group called {}", name).into());
         }
+        Ok(())
+    }
 
-        let window_types = self.connection.get_window_types(&window_id);
+    fn groups_changed(&mut self) {
+        self.activate_current_groups();
+        self.update_ewmh_desktops();
+        self.notify(Notification::GroupsChanged);
+    }
 
-        let dock = window_types.contains(&WindowType::Dock);
-        self.connection
-            .enable_window_key_events(&window_id, &self.keys);
+    fn find_group(&self, group: &GroupSelector) -> Result<GroupId> {
+        group
+            .find(&self.groups)
+            .ok_or_else(|| format!("No such group: {:?}", group).into())
+    }
 
-        let attrs = self.connection.get_window_attributes(&window_id);
-        match attrs {
-            Ok(wattrs) => {
-                if wattrs.override_redirect() {
-                    return;
-                }
+    fn move_focused_to_group(&mut self, gid: GroupId, follow: bool) {
+        if let Some(id) = self.focused_window() {
+            self.move_window_to_group(id, gid);
+            if follow {
+                self.groups[gid].focused_window = Some(id);
             }
+        }
+        if follow {
+            self.focus_group(gid);
+        }
+        self.activate_current_groups();
+    }
+
+    fn shift_focused(&mut self, fun: impl FnOnce(usize, usize) -> Option<usize>) {
+        if let Some(gid) = self.group_idx().and_then(|idx| fun(idx, self.groups.len())) {
+            self.move_focused_to_group(gid, true);
+        }
+    }
+
+    pub fn move_focused_to_next_group(&mut self) {
+        self.shift_focused(|cur, len| if cur + 1 < len { Some(cur + 1) } else { None });
+    }
+
+    pub fn move_focused_to_prev_group(&mut self) {
+        self.shift_focused(|idx, _| idx.checked_sub(1));
+    }
+
+    /// Moves the focused window to the selected group, and shows that group
+    /// too if `follow` is set.
+    pub fn move_focused_to(&mut self, group: &GroupSelector, follow: bool) -> Result<()> {
+        let gid = self.find_group(group)?;
+        self.move_focused_to_group(gid, follow);
+        Ok(())
+    }
+
+    fn is_window_managed(&self, window_id: &WindowId) -> bool {
+        self.windows.iter().find(|w| &w.id == window_id).is_some()
+    }
+
+    pub fn manage_window(&mut self, window_id: WindowId) {
+        self.manage_window_in(window_id, None)
+    }
+
+    /// Manages a window, adding it to `group` or to the active group if
+    /// `None`.
+    fn manage_window_in(&mut self, window_id: WindowId, group: Option<GroupId>) {
+        debug!("Managing window: {}", window_id);
+
+        // If we are already managing the window, then do nothing. We do not
+        // want the window to end up in two groups at once. We shouldn't
+        // be called in such cases, so treat it as an error.
+        if self.is_window_managed(&window_id) {
+            error!(
+                "Asked to manage window that's already managed: {}",
+                window_id
+            );
+            return;
+        }
+
+        let window_types = self.connection.get_window_types(&window_id);
+
+        let dock = window_types.contains(&WindowType::Dock);
+        self.connection
+            .enable_window_key_events(&window_id, &self.keys.key_combos());
+
+        match self.connection.is_override_redirect(&window_id) {
+            Ok(true) => return,
+            Ok(false) => (),
             Err(e) => {
                 warn!("Could not get window attrs for {}: {}", window_id, e);
                 return;
@@ -489,27 +1439,171 @@ impl Lanta {
         }
         if window_types.contains(&WindowType::Notification)
             || window_types.contains(&WindowType::Tooltip)
-            || window_types.contains(&WindowType::Utility)
         {
             return;
         }
 
         if dock {
             self.connection.map_window(&window_id);
-            self.screen.add_dock(&self.connection, window_id);
+            self.screen.add_dock(self.connection.as_ref(), window_id);
+            self.update_ewmh_geometry();
             self.activate_current_groups();
         } else {
+            // Dialogs go with the window they belong to.
+            let parent = self
+                .connection
+                .get_transient_for(&window_id)
+                .and_then(|p| self.windows.iter().find(|w| w.id == p))
+                .map(|w| (w.id, w.group));
+            // There is no point tiling a window that cannot be resized.
+            let mut float = window_types.iter().any(|t| FLOATING_TYPES.contains(t))
+                || self.connection.get_size_hints(&window_id).is_fixed()
+                || parent.is_some();
+            // Clients may ask to start out fullscreen.
+            let mut fullscreen = self
+                .connection
+                .get_window_states(&window_id)
+                .contains(&WindowState::Fullscreen);
+            let mut focus = true;
+            let mut rule_group = None;
+            if !self.rules.is_empty() {
+                let properties = WindowProperties::of(self.connection.as_ref(), &window_id);
+                for action in rules::actions_for(&self.rules, &properties) {
+                    match action {
+                        Action::Group(name) => {
+                            match self.groups.iter().position(|g| g.name() == name) {
+                                Some(gid) => rule_group = Some(gid),
+                                None => warn!("No group named {} for window {}", name, window_id),
+                            }
+                        }
+                        Action::Float => float = true,
+                        Action::Ignore => {
+                            self.connection.map_window(&window_id);
+                            return;
+                        }
+                        Action::NoFocus => focus = false,
+                        Action::Fullscreen => fullscreen = true,
+                    }
+                }
+            }
+
             self.connection.enable_window_tracking(&window_id);
-            self.add_window_to_active_group(window_id);
+            let parent_group = parent.map(|(_id, gid)| gid);
+            let target = group.or(parent_group).or(rule_group);
+            if let Some(gid) = target.or_else(|| self.group_idx()) {
+                let floating = if float {
+                    let parent = parent.filter(|&(_id, g)| g == gid).map(|(id, _gid)| id);
+                    self.float_geometry(&window_id, gid, parent)
+                } else {
+                    None
+                };
+                self.add_window_to_group(window_id, gid, floating);
+                if focus && parent.is_some() {
+                    self.groups[gid].focused_window = Some(window_id);
+                } else if !focus {
+                    let others = self.windows.in_group(gid);
+                    let group = &mut self.groups[gid];
+                    if group.focused_window == Some(window_id) {
+                        group.focused_window = others.into_iter().find(|&w| w != window_id);
+                    }
+                }
+            }
+            if fullscreen {
+                self.set_fullscreen(&window_id, true);
+            }
             self.activate_current_groups();
         }
     }
 
+    /// Returns the viewport of the screen showing `gid`, or of the current
+    /// screen if `gid` isn't shown.
+    fn group_viewport(&self, gid: GroupId) -> Option<Viewport> {
+        let current = self.group_idx();
+        let groups: Vec<_> = self.crtc.values().map(|(_info, gid)| *gid).collect();
+        let vps = self.viewports();
+        groups
+            .iter()
+            .position(|&g| g == gid)
+            .or_else(|| groups.iter().position(|&g| Some(g) == current))
+            .and_then(|idx| vps.get(idx).cloned())
+    }
+
+    /// Centers a newly floating window on the screen showing `gid`, or over
+    /// `parent` if it is visible, at the size it asked for.
+    fn float_geometry(
+        &self,
+        window_id: &WindowId,
+        gid: GroupId,
+        parent: Option<WindowId>,
+    ) -> Option<Viewport> {
+        let screen = self.group_viewport(gid)?;
+        let requested = self.connection.get_window_geometry(window_id);
+        let width = requested.map_or(screen.width / 2, |g| cmp::min(g.width, screen.width));
+        let height = requested.map_or(screen.height / 2, |g| cmp::min(g.height, screen.height));
+        let over = parent
+            .and_then(|p| self.mapped.iter().find(|w| w.id == p))
+            .map_or(screen, |w| w.vp);
+        // Keep the window on the screen, even if its parent is smaller.
+        let center = |start: u32, length: u32, size: u32, screen_start: u32, screen_length| {
+            let centered = (start + length / 2).saturating_sub(size / 2);
+            cmp::min(centered.saturating_sub(screen_start), screen_length - size)
+        };
+        Some(Viewport {
+            x: center(over.x, over.width, width, screen.x, screen.width),
+            y: center(over.y, over.height, height, screen.y, screen.height),
+            width,
+            height,
+        })
+    }
+
+    /// Floats the focused window where it currently is, or puts it back into
+    /// the layout if it is already floating.
+    pub fn toggle_floating(&mut self) {
+        let (id, gid) = match self.focused_window().zip(self.group_idx()) {
+            Some(focused) => focused,
+            None => return,
+        };
+        let current = self.mapped.iter().find(|w| w.id == id).map(|w| w.vp);
+        let screen = self.group_viewport(gid);
+        if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+            window.floating = match (window.floating, current, screen) {
+                (None, Some(vp), Some(screen)) => Some(Viewport {
+                    x: vp.x.saturating_sub(screen.x),
+                    y: vp.y.saturating_sub(screen.y),
+                    ..vp
+                }),
+                _ => None,
+            };
+        }
+        self.activate_current_groups();
+    }
+
+    /// Makes a window cover its screen, or puts it back where it was.
+    fn set_fullscreen(&mut self, window_id: &WindowId, fullscreen: bool) {
+        if let Some(window) = self.windows.iter_mut().find(|w| &w.id == window_id) {
+            window.fullscreen = fullscreen;
+            self.set_window_state(window_id, WindowState::Fullscreen, fullscreen);
+            self.activate_current_groups();
+        }
+    }
+
+    /// Adds or removes one of the window's `_NET_WM_STATE`s.
+    fn set_window_state(&self, window_id: &WindowId, state: WindowState, enabled: bool) {
+        let mut states = self.connection.get_window_states(window_id);
+        states.retain(|s| s != &state);
+        if enabled {
+            states.push(state);
+        }
+        self.connection.set_window_states(window_id, &states);
+    }
+
     pub fn unmanage_window(&mut self, window_id: &WindowId) {
         debug!("Unmanaging window: {}", window_id);
         // Remove the window from whichever Group it is in. Special case for
         // docks which aren't in any group.
-        self.screen.remove_dock(window_id);
+        if self.screen.remove_dock(window_id) {
+            self.update_ewmh_geometry();
+        }
         if self.is_window_managed(window_id) {
             self.remove_window(window_id);
         }
@@ -520,16 +1614,39 @@ impl Lanta {
     pub fn run(mut self) {
         info!("Started WM, entering event loop.");
         let event_loop_connection = self.connection.clone();
-        let event_loop = event_loop_connection.get_event_loop();
+        let watched: Vec<RawFd> = self
+            .ipc
+            .iter()
+            .map(|i| i.server.as_raw_fd())
+            .chain(self.reload.iter().map(|r| r.hangup.as_raw_fd()))
+            .chain(std::iter::once(self.timer.as_raw_fd()))
+            .collect();
+        let event_loop = event_loop_connection.get_event_loop(&watched);
         for event in event_loop {
             match event {
                 Event::MapRequest(window_id) => self.on_map_request(window_id),
+                Event::ConfigureRequest(request) => self.on_configure_request(&request),
                 Event::UnmapNotify(window_id) => self.on_unmap_notify(&window_id),
                 Event::DestroyNotify(window_id) => self.on_destroy_notify(&window_id),
                 Event::KeyPress(key) => self.on_key_press(key),
                 Event::EnterNotify(window_id) => self.on_enter_notify(&window_id),
                 Event::CrtcChange(change) => self.on_crtc_change(&change),
+                Event::CurrentDesktopRequest(group) => self.on_current_desktop_request(group),
+                Event::NumberOfDesktopsRequest(count) => self.on_number_of_desktops_request(count),
+                Event::ActiveWindowRequest(window_id) => self.on_active_window_request(&window_id),
+                Event::CloseWindowRequest(window_id) => self.on_close_window_request(&window_id),
+                Event::WindowDesktopRequest(window_id, group) => {
+                    self.on_window_desktop_request(&window_id, group)
+                }
+                Event::StateRequest(window_id, action, states) => {
+                    self.on_state_request(&window_id, action, &states)
+                }
+                Event::PingReply(window_id) => self.on_ping_reply(&window_id),
+                Event::HintsChanged(window_id) => self.on_hints_changed(&window_id),
+                Event::SizeHintsChanged(window_id) => self.on_size_hints_changed(&window_id),
+                Event::Readable(fd) => self.on_readable(fd),
             }
+            self.flush_subscribers();
             self.children = self
                 .children
                 .into_iter()
@@ -568,6 +1685,8 @@ impl Lanta {
         // (should) do it by disabling event tracking first.
         if self.is_window_managed(window_id) {
             self.remove_window(window_id);
+            self.connection.set_wm_state(window_id, WmState::Withdrawn);
+            self.connection.remove_window_desktop(window_id);
         }
     }
 
@@ -575,6 +1694,158 @@ impl Lanta {
         self.unmanage_window(window_id);
     }
 
+    fn on_current_desktop_request(&mut self, group: GroupId) {
+        self.focus_group(group);
+        self.activate_current_groups();
+    }
+
+    /// Adds numbered groups at the end, or removes the last groups, until
+    /// there are `count` of them.
+    fn on_number_of_desktops_request(&mut self, count: usize) {
+        while self.groups.len() < count {
+            let name = (self.groups.len() + 1..)
+                .map(|n| n.to_string())
+                .find(|name| self.groups.iter().all(|g| g.name() != name))
+                .expect("There is an unused number");
+            if let Err(e) = self.add_group(&name, None) {
+                warn!("Could not add group: {}", e);
+                return;
+            }
+        }
+        while self.groups.len() > count {
+            let last = GroupSelector::Index(self.groups.len() - 1);
+            if let Err(e) = self.remove_group(&last) {
+                warn!("Could not remove group: {}", e);
+                return;
+            }
+        }
+    }
+
+    /// Focuses a window, first bringing its group onto the current screen if
+    /// it isn't shown on any.
+    fn on_active_window_request(&mut self, window_id: &WindowId) {
+        self.show_window(window_id);
+    }
+
+    /// Focuses a window, first showing its group if it is hidden.
+    fn show_window(&mut self, window_id: &WindowId) {
+        let group = match self.windows.iter().find(|w| &w.id == window_id) {
+            Some(w) => w.group,
+            None => return,
+        };
+        if !self.crtc.values().any(|(_info, gid)| *gid == group) {
+            self.focus_group(group);
+        }
+        self.focus_window(window_id);
+    }
+
+    fn on_close_window_request(&mut self, window_id: &WindowId) {
+        if self.is_window_managed(window_id) {
+            self.close_window(*window_id);
+        }
+    }
+
+    fn on_window_desktop_request(&mut self, window_id: &WindowId, group: GroupId) {
+        // Requests to show a window on every desktop are ignored.
+        if group < self.groups.len() && self.is_window_managed(window_id) {
+            self.move_window_to_group(*window_id, group);
+            self.activate_current_groups();
+            self.update_ewmh_desktops();
+        }
+    }
+
+    fn on_configure_request(&mut self, request: &ConfigureRequest) {
+        let window = self.windows.iter().find(|w| w.id == request.window);
+        let mapped = self.mapped.iter().find(|w| w.id == request.window);
+        match window {
+            // Tiled and fullscreen windows stay where they were put, and ICCCM
+            // says that the client must be told so.
+            Some(w) if w.floating.is_none() || w.fullscreen => match mapped {
+                Some(mapped) => self
+                    .connection
+                    .send_configure_notify(&mapped.id, &mapped.vp),
+                // Windows in hidden groups keep their current geometry too.
+                None => {
+                    if let Some(geometry) = self.connection.get_window_geometry(&w.id) {
+                        self.connection.send_configure_notify(&w.id, &geometry);
+                    }
+                }
+            },
+            Some(w) => {
+                // Remember where the window moved to, relative to its screen,
+                // so that the next relayout leaves it there.
+                let screen = self.group_viewport(w.group);
+                let (origin_x, origin_y) = screen.map_or((0, 0), |vp| (vp.x, vp.y));
+                let relative = |pos: i32, origin: u32| cmp::max(pos - origin as i32, 0) as u32;
+                let window = self.windows.iter_mut().find(|w| w.id == request.window);
+                if let Some(vp) = window.and_then(|w| w.floating.as_mut()) {
+                    vp.x = request.x.map_or(vp.x, |x| relative(x, origin_x));
+                    vp.y = request.y.map_or(vp.y, |y| relative(y, origin_y));
+                    vp.width = request.width.unwrap_or(vp.width);
+                    vp.height = request.height.unwrap_or(vp.height);
+                }
+                self.connection.grant_configure_request(request);
+            }
+            None => self.connection.grant_configure_request(request),
+        }
+    }
+
+    fn on_hints_changed(&mut self, window_id: &WindowId) {
+        // The focused window already has the user's attention.
+        let urgent =
+            self.connection.is_urgent(window_id) && self.focused_window() != Some(*window_id);
+        self.set_urgency(window_id, Some(urgent), None);
+    }
+
+    /// Fits the window to its new size hints, as terminals change their
+    /// increments along with their font.
+    fn on_size_hints_changed(&mut self, window_id: &WindowId) {
+        let size_hints = self.connection.get_size_hints(window_id);
+        if let Some(window) = self.windows.iter_mut().find(|w| &w.id == window_id) {
+            if window.size_hints != size_hints {
+                window.size_hints = size_hints;
+                self.activate_current_groups();
+            }
+        }
+    }
+
+    fn on_state_request(
+        &mut self,
+        window_id: &WindowId,
+        action: StateAction,
+        states: &[WindowState],
+    ) {
+        let (fullscreen, modal) = match self.windows.iter().find(|w| &w.id == window_id) {
+            Some(w) => (w.fullscreen, w.modal),
+            None => return,
+        };
+        let apply = |current: bool| match action {
+            StateAction::Add => true,
+            StateAction::Remove => false,
+            StateAction::Toggle => !current,
+        };
+        if states.contains(&WindowState::Fullscreen) {
+            self.set_fullscreen(window_id, apply(fullscreen));
+        }
+        if states.contains(&WindowState::DemandsAttention) {
+            let attention = self
+                .windows
+                .iter()
+                .find(|w| &w.id == window_id)
+                .is_some_and(|w| w.demands_attention);
+            let attention = apply(attention) && self.focused_window() != Some(*window_id);
+            self.set_urgency(window_id, None, Some(attention));
+        }
+        if states.contains(&WindowState::Modal) {
+            let modal = apply(modal);
+            if let Some(window) = self.windows.iter_mut().find(|w| &w.id == window_id) {
+                window.modal = modal;
+            }
+            self.set_window_state(window_id, WindowState::Modal, modal);
+            self.activate_current_groups();
+        }
+    }
+
     fn on_key_press(&mut self, key: KeyCombo) {
         if let Some(handler) = self.keys.get(&key) {
             if let Err(error) = (handler)(self) {
@@ -583,6 +1854,170 @@ impl Lanta {
         }
     }
 
+    fn on_readable(&mut self, fd: RawFd) {
+        if fd == self.timer.as_raw_fd() {
+            return self.on_timer();
+        }
+        let hangup = self.reload.as_ref().filter(|r| r.hangup.as_raw_fd() == fd);
+        if let Some(reload) = hangup {
+            reload.hangup.drain();
+            info!("Received SIGHUP, reloading the configuration");
+            if let Err(e) = self.reload() {
+                error!("Could not reload the configuration: {}", e);
+            }
+        } else {
+            self.on_ipc_readable();
+        }
+    }
+
+    fn on_ipc_readable(&mut self) {
+        let clients = match self.ipc {
+            Some(ref ipc) => ipc.server.accept(),
+            None => return,
+        };
+        for client in clients {
+            self.on_ipc_client(client);
+        }
+    }
+
+    fn on_ipc_client(&mut self, mut client: ipc::Client) {
+        let reply = match client.receive() {
+            Ok(request) => {
+                debug!("IPC request: {:?}", request);
+                match request {
+                    Request::Command(text) => self.run_ipc_command(&text),
+                    Request::GetTree => Reply::Tree(self.tree()),
+                    Request::Subscribe => return self.subscribe(client),
+                }
+            }
+            Err(e) => Reply::Error(format!("Could not read request: {}", e)),
+        };
+        if let Err(e) = client.send(&reply) {
+            warn!("Could not reply to IPC client: {}", e);
+        }
+    }
+
+    fn run_ipc_command(&mut self, text: &str) -> Reply {
+        let command = match self.ipc {
+            Some(ref ipc) => (ipc.parse)(text),
+            None => return Reply::Error("Not listening for commands".into()),
+        };
+        match command.and_then(|command| command(self).map_err(Into::into)) {
+            Ok(()) => Reply::Ok,
+            Err(e) => Reply::Error(e.to_string()),
+        }
+    }
+
+    fn subscribe(&mut self, client: ipc::Client) {
+        // Subscribers are never read from again.
+        let subscribed = client
+            .send(&Reply::Ok)
+            .and_then(|()| Ok(ipc::Subscriber::new(client)?));
+        match (subscribed, self.ipc.as_mut()) {
+            (Ok(subscriber), Some(ipc)) => ipc.subscribers.push(subscriber),
+            (Err(e), _) => warn!("Could not subscribe IPC client: {}", e),
+            (Ok(_), None) => (),
+        }
+    }
+
+    /// Sends a notification to every subscriber, dropping any that have gone
+    /// away or fallen behind.
+    fn notify(&mut self, notification: Notification) {
+        if let Some(ref mut ipc) = self.ipc {
+            ipc.subscribers
+                .retain_mut(|subscriber| match subscriber.send(&notification) {
+                    Ok(()) => !subscriber.is_finished(),
+                    Err(e) => {
+                        info!("Dropping IPC subscriber: {}", e);
+                        false
+                    }
+                });
+        }
+    }
+
+    /// Writes the notifications that subscribers couldn't take earlier.
+    fn flush_subscribers(&mut self) {
+        if let Some(ref mut ipc) = self.ipc {
+            ipc.subscribers.retain_mut(|subscriber| {
+                if !subscriber.has_pending() {
+                    return true;
+                }
+                match subscriber.flush() {
+                    Ok(()) => !subscriber.is_finished(),
+                    Err(e) => {
+                        info!("Dropping IPC subscriber: {}", e);
+                        false
+                    }
+                }
+            });
+        }
+    }
+
+    /// Describes the state of the window manager for IPC clients.
+    pub fn tree(&self) -> Tree {
+        let mut crtcs: Vec<_> = self
+            .crtc
+            .iter()
+            .map(|(&crtc, (info, group))| CrtcGroup {
+                crtc,
+                info: info.clone(),
+                group: *group,
+            })
+            .collect();
+        crtcs.sort_by_key(|c| c.crtc);
+        Tree {
+            groups: self
+                .groups
+                .iter()
+                .enumerate()
+                .map(|(gid, g)| GroupInfo {
+                    name: g.name().to_owned(),
+                    layout_id: g.layout_id,
+                    layout: self
+                        .layouts
+                        .get(g.layout_id)
+                        .map(|l| l.name().to_owned())
+                        .unwrap_or_default(),
+                    focused_window: g.focused_window,
+                    urgent: self
+                        .windows
+                        .iter()
+                        .any(|w| w.group == gid && self.urgent.contains(&w.id)),
+                })
+                .collect(),
+            windows: self
+                .windows
+                .iter()
+                .map(|w| WindowInfo {
+                    id: w.id,
+                    group: w.group,
+                    floating: w.floating.is_some(),
+                    fullscreen: w.fullscreen,
+                    urgent: self.urgent.contains(&w.id),
+                })
+                .collect(),
+            crtcs,
+            current_crtc: self.current_crtc,
+            mapped: self
+                .mapped
+                .iter()
+                .map(|w| MappedInfo {
+                    id: w.id,
+                    viewport: w.vp,
+                })
+                .collect(),
+            docks: self
+                .screen
+                .docks()
+                .iter()
+                .map(|d| DockInfo {
+                    id: d.window_id(),
+                    strut: d.strut().cloned(),
+                })
+                .collect(),
+        }
+    }
+
     fn on_enter_notify(&mut self, window_id: &WindowId) {
         self.focus_window(window_id);
     }
@@ -594,23 +2029,1273 @@ impl Lanta {
         );
         if change.width > 0 && change.height > 0 {
             let gidx = self.find_next_unallocated_group();
-            match self.crtc.entry(change.crtc) {
+            let notification = match self.crtc.entry(change.crtc) {
                 Entry::Vacant(v) => {
                     v.insert((change.into(), gidx));
+                    Notification::CrtcAdded {
+                        crtc: change.crtc,
+                        info: change.into(),
+                        group: gidx,
+                    }
                 }
                 Entry::Occupied(ref mut o) => {
                     o.get_mut().0 = change.into();
+                    Notification::CrtcChanged {
+                        crtc: change.crtc,
+                        info: change.into(),
+                    }
                 }
-            }
+            };
+            self.notify(notification);
         } else {
-            self.crtc.remove(&change.crtc);
+            self.group_history.remove(&change.crtc);
+            if self.crtc.remove(&change.crtc).is_some() {
+                self.notify(Notification::CrtcRemoved { crtc: change.crtc });
+            }
             if self.current_crtc == Some(change.crtc) {
                 self.current_crtc = self.crtc.keys().next().cloned();
             }
         }
+        self.update_ewmh_geometry();
         debug!(
             "Crtc's Changed! After: {:?}, {:?}",
             &self.crtc, &self.current_crtc
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::layout::{StackLayout, ThreeColumn};
+    use crate::x::fake::{FakeConnection, FakeWindow, Request};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    fn crtc_info(x: i16, width: u16, height: u16) -> CrtcInfo {
+        CrtcInfo {
+            x,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    fn lanta(crtc: Vec<(Crtc, CrtcInfo)>) -> Lanta<FakeConnection> {
+        lanta_with(FakeConnection::new(crtc))
+    }
+
+    fn lanta_with(conn: FakeConnection) -> Lanta<FakeConnection> {
+        lanta_from(conn, Vec::new(), None)
+    }
+
+    fn lanta_from(
+        conn: FakeConnection,
+        rules: Vec<Rule>,
+        saved: Option<SavedState>,
+    ) -> Lanta<FakeConnection> {
+        let layouts: Vec<Box<dyn Layout<WindowId>>> = vec![
+            Box::new(ThreeColumn::new("3-column", 0)),
+            Box::new(StackLayout::new("stack", 0)),
+        ];
+        let groups: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|&name| Group::new(name, "3-column", &layouts))
+            .collect();
+        let keys = KeyHandlers::from(Vec::new());
+        Lanta::with_connection(conn, keys, groups, layouts, rules, saved).unwrap()
+    }
+
+    fn viewport_of(wm: &Lanta<FakeConnection>, id: WindowId) -> Option<Viewport> {
+        wm.mapped.iter().find(|w| w.id == id).map(|w| w.vp)
+    }
+
+    #[test]
+    fn existing_windows_are_adopted_on_startup() {
+        let conn = FakeConnection::new(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = conn.add_top_level_window(10, FakeWindow::default());
+        let b = conn.add_top_level_window(
+            11,
+            FakeWindow {
+                override_redirect: true,
+                ..Default::default()
+            },
+        );
+        let wm = lanta_with(conn);
+        assert_eq!(wm.windows.in_group(0), vec![a]);
+        assert!(wm.connection.is_mapped(&a));
+        assert!(!wm.connection.is_mapped(&b));
+    }
+
+    #[test]
+    fn existing_windows_return_to_their_desktop() {
+        let conn = FakeConnection::new(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = conn.add_top_level_window(
+            10,
+            FakeWindow {
+                desktop: Some(2),
+                ..Default::default()
+            },
+        );
+        let b = conn.add_top_level_window(
+            11,
+            FakeWindow {
+                desktop: Some(7),
+                ..Default::default()
+            },
+        );
+        let mut wm = lanta_with(conn);
+        assert_eq!(wm.windows.in_group(2), vec![a]);
+        assert_eq!(wm.windows.in_group(0), vec![b]);
+        assert_eq!(wm.connection.get_window_desktop(&b), Some(0));
+
+        wm.on_window_desktop_request(&a, 1);
+        assert_eq!(wm.connection.get_window_desktop(&a), Some(1));
+    }
+
+    #[test]
+    fn tiled_windows_respect_their_size_hints() {
+        let conn = FakeConnection::new(vec![(1, crtc_info(0, 1920, 1080))]);
+        let terminal = conn.add_top_level_window(
+            10,
+            FakeWindow {
+                size_hints: SizeHints {
+                    increment: Some((7, 13)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let fixed = conn.add_top_level_window(
+            11,
+            FakeWindow {
+                size_hints: SizeHints {
+                    min: Some((300, 200)),
+                    max: Some((300, 200)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let wm = lanta_with(conn);
+        assert_eq!(
+            viewport_of(&wm, terminal),
+            Some(Viewport {
+                x: 1,
+                y: 0,
+                width: 1918,
+                height: 1079,
+            })
+        );
+        assert!(wm
+            .windows
+            .iter()
+            .any(|w| w.id == fixed && w.floating.is_some()));
+    }
+
+    #[test]
+    fn tiled_windows_follow_changes_to_their_size_hints() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let terminal = wm.connection.add_window(10, FakeWindow::default());
+        wm.manage_window(terminal);
+        assert_eq!(viewport_of(&wm, terminal).unwrap().width, 1920);
+
+        wm.connection.update_window(&terminal, |w| {
+            w.size_hints.increment = Some((7, 13));
+        });
+        wm.connection.take_requests();
+        wm.on_size_hints_changed(&terminal);
+        let fitted = Viewport {
+            x: 1,
+            y: 0,
+            width: 1918,
+            height: 1079,
+        };
+        assert_eq!(viewport_of(&wm, terminal), Some(fitted));
+        assert!(wm
+            .connection
+            .take_requests()
+            .contains(&Request::Configure(terminal, fitted)));
+    }
+
+    #[test]
+    fn wm_state_follows_windows_between_groups() {
+        let conn = FakeConnection::new(vec![(1, crtc_info(0, 1920, 1080))]);
+        let withdrawn = conn.add_top_level_window(
+            10,
+            FakeWindow {
+                wm_state: Some(WmState::Withdrawn),
+                ..Default::default()
+            },
+        );
+        let a = conn.add_top_level_window(11, FakeWindow::default());
+        let mut wm = lanta_with(conn);
+        assert!(!wm.is_window_managed(&withdrawn));
+        assert_eq!(wm.connection.get_wm_state(&a), Some(WmState::Normal));
+
+        wm.next_group();
+        assert_eq!(wm.connection.get_wm_state(&a), Some(WmState::Iconic));
+        wm.prev_group();
+        assert_eq!(wm.connection.get_wm_state(&a), Some(WmState::Normal));
+
+        wm.connection.take_requests();
+        wm.on_unmap_notify(&a);
+        assert!(!wm.is_window_managed(&a));
+        assert_eq!(wm.connection.get_wm_state(&a), Some(WmState::Withdrawn));
+        assert!(wm
+            .connection
+            .take_requests()
+            .contains(&Request::RemoveDesktop(a)));
+        assert_eq!(wm.connection.get_window_desktop(&a), None);
+    }
+
+    #[test]
+    fn manage_window_adds_to_active_group_and_focuses() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+        assert_eq!(wm.windows.in_group(0), vec![a, b]);
+        assert_eq!(wm.focused_window(), Some(a));
+        assert!(wm.connection.is_mapped(&a));
+        assert!(wm.connection.is_mapped(&b));
+        assert_eq!(
+            viewport_of(&wm, a),
+            Some(Viewport {
+                x: 0,
+                y: 0,
+                width: 640,
+                height: 1080
+            })
+        );
+        let requests = wm.connection.take_requests();
+        assert_eq!(requests.last(), Some(&Request::Focus(Some(a))));
+    }
+
+    #[test]
+    fn manage_window_ignores_notifications_and_reserves_docks() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let notification = wm.connection.add_window(
+            10,
+            FakeWindow {
+                types: vec![WindowType::Notification],
+                ..Default::default()
+            },
+        );
+        let dock = wm.connection.add_window(
+            11,
+            FakeWindow {
+                types: vec![WindowType::Dock],
+                strut: Some(viewport::Strut {
+                    left: 0,
+                    right: 0,
+                    top: 30,
+                    bottom: 0,
+                    left_start_y: 0,
+                    left_end_y: 0,
+                    right_start_y: 0,
+                    right_end_y: 0,
+                    top_start_x: 0,
+                    top_end_x: 1919,
+                    bottom_start_x: 0,
+                    bottom_end_x: 0,
+                }),
+                ..Default::default()
+            },
+        );
+        let a = wm.connection.add_window(12, FakeWindow::default());
+        wm.manage_window(notification);
+        wm.connection.take_requests();
+        wm.manage_window(dock);
+        let usable = Viewport {
+            x: 0,
+            y: 30,
+            width: 1920,
+            height: 1050,
+        };
+        assert!(wm
+            .connection
+            .take_requests()
+            .contains(&Request::UpdateGeometry {
+                width: 1920,
+                height: 1080,
+                workarea: vec![usable; 3],
+            }));
+        wm.manage_window(a);
+        assert!(!wm.is_window_managed(&notification));
+        assert!(!wm.is_window_managed(&dock));
+        assert!(wm.connection.is_mapped(&dock));
+        assert_eq!(viewport_of(&wm, a), Some(usable));
+
+        wm.connection.take_requests();
+        wm.unmanage_window(&dock);
+        let full = Viewport::clone_from_crtc_info(&crtc_info(0, 1920, 1080));
+        assert!(wm
+            .connection
+            .take_requests()
+            .contains(&Request::UpdateGeometry {
+                width: 1920,
+                height: 1080,
+                workarea: vec![full; 3],
+            }));
+    }
+
+    #[test]
+    fn groups_can_be_reached_by_index_or_name() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+
+        wm.go_to_group(&GroupSelector::Index(2)).unwrap();
+        assert_eq!(wm.group_idx(), Some(2));
+        wm.go_to_group(&GroupSelector::Name("a".to_owned()))
+            .unwrap();
+        assert_eq!(wm.group_idx(), Some(0));
+        assert!(wm.go_to_group(&GroupSelector::Index(3)).is_err());
+        assert!(wm
+            .go_to_group(&GroupSelector::Name("mail".to_owned()))
+            .is_err());
+        assert_eq!(wm.group_idx(), Some(0));
+
+        wm.focus_window(&a);
+        wm.move_focused_to(&GroupSelector::Name("c".to_owned()), false)
+            .unwrap();
+        assert_eq!(wm.windows.in_group(2), vec![a]);
+        assert_eq!(wm.group_idx(), Some(0));
+        assert_eq!(wm.focused_window(), Some(b));
+
+        wm.move_focused_to(&GroupSelector::Index(2), true).unwrap();
+        assert_eq!(wm.windows.in_group(2), vec![a, b]);
+        assert_eq!(wm.group_idx(), Some(2));
+        assert_eq!(wm.focused_window(), Some(b));
+    }
+
+    #[test]
+    fn focus_group_swaps_with_other_crtc() {
+        let mut wm = lanta(vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1920, 1080)),
+        ]);
+        let current = wm.current_crtc.unwrap();
+        let other = if current == 1 { 2 } else { 1 };
+        let (old, shown_elsewhere) = (wm.crtc[&current].1, wm.crtc[&other].1);
+        wm.focus_group(shown_elsewhere);
+        assert_eq!(wm.crtc[&current].1, shown_elsewhere);
+        assert_eq!(wm.crtc[&other].1, old);
+    }
+
+    #[test]
+    fn view_policy_moves_focus_to_other_crtc() {
+        let mut wm = lanta(vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1920, 1080)),
+        ]);
+        wm.set_group_policy(GroupPolicy::View);
+        let current = wm.current_crtc.unwrap();
+        let other = if current == 1 { 2 } else { 1 };
+        let (old, shown_elsewhere) = (wm.crtc[&current].1, wm.crtc[&other].1);
+        wm.focus_group(shown_elsewhere);
+        assert_eq!(wm.current_crtc, Some(other));
+        assert_eq!(wm.crtc[&current].1, old);
+        assert_eq!(wm.crtc[&other].1, shown_elsewhere);
+    }
+
+    #[test]
+    fn pinned_policy_shows_hidden_group_on_its_last_crtc() {
+        let mut wm = lanta(vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1920, 1080)),
+        ]);
+        wm.set_group_policy(GroupPolicy::Pinned);
+        let current = wm.current_crtc.unwrap();
+        let other = if current == 1 { 2 } else { 1 };
+        let hidden = (0..3)
+            .find(|gid| wm.crtc.values().all(|(_info, g)| g != gid))
+            .unwrap();
+        wm.groups[hidden].last_crtc = Some(other);
+        wm.focus_group(hidden);
+        assert_eq!(wm.current_crtc, Some(other));
+        assert_eq!(wm.crtc[&other].1, hidden);
+    }
+
+    #[test]
+    fn switching_groups_unmaps_hidden_windows() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        wm.manage_window(a);
+        wm.next_group();
+        assert_eq!(wm.group_idx(), Some(1));
+        assert!(!wm.connection.is_mapped(&a));
+        wm.prev_group();
+        assert!(wm.connection.is_mapped(&a));
+    }
+
+    #[test]
+    fn group_last_toggles_between_two_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        wm.group_last();
+        assert_eq!(wm.group_idx(), Some(0));
+        wm.go_to_group(&GroupSelector::Index(2)).unwrap();
+        wm.go_to_group(&GroupSelector::Index(1)).unwrap();
+        wm.group_last();
+        assert_eq!(wm.group_idx(), Some(2));
+        wm.group_last();
+        assert_eq!(wm.group_idx(), Some(1));
+
+        wm.go_to_group(&GroupSelector::Index(1)).unwrap();
+        assert_eq!(wm.group_idx(), Some(1));
+        wm.set_back_and_forth(true);
+        wm.go_to_group(&GroupSelector::Index(1)).unwrap();
+        assert_eq!(wm.group_idx(), Some(2));
+    }
+
+    #[test]
+    fn swap_windows_exchanges_viewports() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+        let (vp_a, vp_b) = (viewport_of(&wm, a), viewport_of(&wm, b));
+        wm.connection.take_requests();
+        wm.swap_windows(a, b);
+        assert_eq!(viewport_of(&wm, a), vp_b);
+        assert_eq!(viewport_of(&wm, b), vp_a);
+        let requests = wm.connection.take_requests();
+        assert!(requests.contains(&Request::Configure(a, vp_b.unwrap())));
+        assert!(requests.contains(&Request::Configure(b, vp_a.unwrap())));
+    }
+
+    #[test]
+    fn dialogs_float_centered_above_tiled_windows() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let dialog = wm.connection.add_window(
+            11,
+            FakeWindow {
+                types: vec![WindowType::Dialog],
+                geometry: Some(Viewport {
+                    x: 0,
+                    y: 0,
+                    width: 400,
+                    height: 300,
+                }),
+                ..Default::default()
+            },
+        );
+        wm.manage_window(a);
+        wm.manage_window(dialog);
+        let b = wm.connection.add_window(12, FakeWindow::default());
+        wm.connection.take_requests();
+        wm.manage_window(b);
+        assert_eq!(wm.windows.tiled_in_group(0), vec![a, b]);
+        assert_eq!(
+            viewport_of(&wm, dialog),
+            Some(Viewport {
+                x: 760,
+                y: 390,
+                width: 400,
+                height: 300
+            })
+        );
+        // The newly mapped window must not cover the dialog.
+        let requests = wm.connection.take_requests();
+        let mapped = requests.iter().position(|r| r == &Request::Map(b));
+        let raised = requests.iter().rposition(|r| r == &Request::Raise(dialog));
+        assert!(mapped < raised);
+    }
+
+    #[test]
+    fn toggle_floating_keeps_the_window_in_place() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+        let vp_a = viewport_of(&wm, a);
+        wm.toggle_floating();
+        assert_eq!(wm.windows.tiled_in_group(0), vec![b]);
+        assert_eq!(viewport_of(&wm, a), vp_a);
+        assert_eq!(viewport_of(&wm, b).unwrap().width, 1920);
+        wm.toggle_floating();
+        assert_eq!(wm.windows.tiled_in_group(0), vec![a, b]);
+        assert_eq!(viewport_of(&wm, a), vp_a);
+    }
+
+    #[test]
+    fn configure_requests_are_refused_for_tiled_windows() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let tiled = wm.connection.add_window(10, FakeWindow::default());
+        let dialog = wm.connection.add_window(
+            11,
+            FakeWindow {
+                types: vec![WindowType::Dialog],
+                ..Default::default()
+            },
+        );
+        let unmanaged = wm.connection.add_window(12, FakeWindow::default());
+        wm.manage_window(tiled);
+        wm.manage_window(dialog);
+        wm.connection.take_requests();
+        let resize = |window| ConfigureRequest {
+            window,
+            x: Some(100),
+            y: Some(50),
+            width: Some(640),
+            height: Some(480),
+            border_width: None,
+            sibling: None,
+            stack_mode: None,
+        };
+
+        wm.on_configure_request(&resize(tiled));
+        let tiled_vp = viewport_of(&wm, tiled).unwrap();
+        assert_eq!(
+            wm.connection.take_requests(),
+            vec![Request::ConfigureNotify(tiled, tiled_vp)]
+        );
+
+        wm.on_configure_request(&resize(dialog));
+        wm.on_configure_request(&resize(unmanaged));
+        assert_eq!(
+            wm.connection.take_requests(),
+            vec![
+                Request::GrantConfigure(resize(dialog)),
+                Request::GrantConfigure(resize(unmanaged)),
+            ]
+        );
+        // The dialog stays where it moved to.
+        wm.activate_current_groups();
+        assert_eq!(
+            viewport_of(&wm, dialog),
+            Some(Viewport {
+                x: 100,
+                y: 50,
+                width: 640,
+                height: 480,
+            })
+        );
+    }
+
+    #[test]
+    fn configure_requests_are_refused_for_tiled_windows_in_hidden_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let geometry = Viewport {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let tiled = wm.connection.add_window(
+            10,
+            FakeWindow {
+                geometry: Some(geometry),
+                ..Default::default()
+            },
+        );
+        wm.manage_window(tiled);
+        wm.next_group();
+        wm.connection.take_requests();
+
+        let request = ConfigureRequest {
+            window: tiled,
+            x: Some(100),
+            y: Some(50),
+            width: Some(640),
+            height: Some(480),
+            border_width: None,
+            sibling: None,
+            stack_mode: None,
+        };
+        wm.on_configure_request(&request);
+        assert_eq!(
+            wm.connection.take_requests(),
+            vec![Request::ConfigureNotify(tiled, geometry)]
+        );
+    }
+
+    #[test]
+    fn focus_follows_the_icccm_input_model() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let models = [
+            InputModel::NoInput,
+            InputModel::Passive,
+            InputModel::LocallyActive,
+            InputModel::GloballyActive,
+        ];
+        let windows: Vec<_> = models
+            .iter()
+            .enumerate()
+            .map(|(i, &input_model)| {
+                let id = wm.connection.add_window(
+                    10 + i as u32,
+                    FakeWindow {
+                        input_model,
+                        ..Default::default()
+                    },
+                );
+                wm.manage_window(id);
+                id
+            })
+            .collect();
+        let focus_requests = |wm: &mut Lanta<FakeConnection>, id| {
+            wm.connection.take_requests();
+            wm.focus_window(&id);
+            wm.connection
+                .take_requests()
+                .into_iter()
+                .filter(|r| match r {
+                    Request::Focus(_) | Request::SetActive(_) | Request::TakeFocus(_) => true,
+                    _ => false,
+                })
+                .collect::<Vec<_>>()
+        };
+        let (none, passive, local, global) = (windows[0], windows[1], windows[2], windows[3]);
+        assert_eq!(
+            focus_requests(&mut wm, none),
+            vec![Request::Focus(None), Request::SetActive(none)]
+        );
+        assert_eq!(
+            focus_requests(&mut wm, passive),
+            vec![Request::Focus(Some(passive))]
+        );
+        assert_eq!(
+            focus_requests(&mut wm, local),
+            vec![Request::Focus(Some(local)), Request::TakeFocus(local)]
+        );
+        assert_eq!(
+            focus_requests(&mut wm, global),
+            vec![Request::SetActive(global), Request::TakeFocus(global)]
+        );
+    }
+
+    #[test]
+    fn pagers_switch_groups_and_focus_windows() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+
+        wm.on_current_desktop_request(2);
+        assert_eq!(wm.group_idx(), Some(2));
+        assert!(!wm.connection.is_mapped(&a));
+
+        wm.on_active_window_request(&b);
+        assert_eq!(wm.group_idx(), Some(0));
+        assert_eq!(wm.focused_window(), Some(b));
+
+        wm.on_window_desktop_request(&b, 1);
+        assert_eq!(wm.windows.in_group(1), vec![b]);
+        assert_eq!(wm.focused_window(), Some(a));
+        assert_eq!(wm.groups[1].focused_window, Some(b));
+        assert!(!wm.connection.is_mapped(&b));
+
+        wm.connection.take_requests();
+        wm.on_close_window_request(&a);
+        assert_eq!(wm.connection.take_requests(), vec![Request::Close(a)]);
+    }
+
+    #[test]
+    fn groups_are_managed_at_runtime() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let names = |wm: &Lanta<FakeConnection>| -> Vec<String> {
+            wm.groups.iter().map(|g| g.name().to_owned()).collect()
+        };
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.focus_group(1);
+        wm.manage_window(b);
+
+        wm.add_group("scratch", Some("stack")).unwrap();
+        assert!(wm.add_group("scratch", None).is_err());
+        assert!(wm.add_group("d", Some("spiral")).is_err());
+        assert_eq!(names(&wm), vec!["a", "b", "c", "scratch"]);
+        assert_eq!(wm.layouts[wm.groups[3].layout_id].name(), "stack");
+
+        let b_group = GroupSelector::Name("b".to_owned());
+        wm.rename_group(&b_group, "web").unwrap();
+        assert!(wm.rename_group(&GroupSelector::Index(0), "web").is_err());
+        let web = GroupSelector::Name("web".to_owned());
+        wm.reorder_group(&web, 3).unwrap();
+        assert_eq!(names(&wm), vec!["a", "c", "scratch", "web"]);
+        assert_eq!(wm.windows.in_group(3), vec![b]);
+        assert_eq!(wm.group_idx(), Some(3));
+
+        wm.remove_group(&GroupSelector::Index(0)).unwrap();
+        assert_eq!(names(&wm), vec!["c", "scratch", "web"]);
+        assert_eq!(wm.windows.in_group(0), vec![a]);
+        assert_eq!(wm.groups[0].focused_window, Some(a));
+        assert_eq!(wm.windows.in_group(2), vec![b]);
+        assert_eq!(wm.group_idx(), Some(2));
+
+        wm.remove_group(&web).unwrap();
+        assert_eq!(wm.windows.in_group(1), vec![b]);
+        assert_eq!(wm.group_idx(), Some(1));
+        assert!(wm.remove_group(&GroupSelector::Index(0)).is_ok());
+        assert!(wm.remove_group(&GroupSelector::Index(0)).is_err());
+    }
+
+    #[test]
+    fn pagers_change_the_number_of_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        wm.focus_group(2);
+        wm.manage_window(a);
+        wm.connection.take_requests();
+
+        wm.on_number_of_desktops_request(5);
+        let names: Vec<&str> = wm.groups.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec!["a", "b", "c", "4", "5"]);
+        assert!(wm
+            .connection
+            .take_requests()
+            .iter()
+            .any(|r| matches!(r, Request::UpdateDesktops { names, .. } if names.len() == 5)));
+
+        wm.on_number_of_desktops_request(2);
+        assert_eq!(wm.groups.len(), 2);
+        assert_eq!(wm.windows.in_group(1), vec![a]);
+        assert_eq!(wm.group_idx(), Some(1));
+        assert!(wm.connection.is_mapped(&a));
+    }
+
+    #[test]
+    fn unresponsive_windows_are_killed_after_the_close_timeout() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let pingable = FakeWindow {
+            ping: true,
+            ..Default::default()
+        };
+        let hung = wm.connection.add_window(10, pingable.clone());
+        let busy = wm.connection.add_window(11, pingable);
+        let old = wm.connection.add_window(12, FakeWindow::default());
+        let gone = wm.connection.add_window(13, FakeWindow::default());
+        for &id in &[hung, busy, old, gone] {
+            wm.manage_window(id);
+        }
+        wm.set_close_timeout(Some(Duration::from_millis(0)));
+        wm.connection.take_requests();
+
+        for id in &[hung, busy, old, gone] {
+            wm.on_close_window_request(id);
+        }
+        wm.on_ping_reply(&busy);
+        wm.unmanage_window(&gone);
+        let requests = wm.connection.take_requests();
+        assert!(requests.contains(&Request::Ping(hung)));
+        assert!(!requests.contains(&Request::Ping(old)));
+
+        wm.on_timer();
+        assert_eq!(
+            wm.connection.take_requests(),
+            vec![Request::Kill(hung), Request::Kill(old)]
+        );
+    }
+
+    #[test]
+    fn dialogs_join_their_parent_and_modal_ones_keep_the_focus() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        let dialog = wm.connection.add_window(
+            12,
+            FakeWindow {
+                transient_for: Some(a),
+                states: vec![WindowState::Modal],
+                geometry: Some(Viewport {
+                    x: 0,
+                    y: 0,
+                    width: 400,
+                    height: 300,
+                }),
+                ..Default::default()
+            },
+        );
+        wm.manage_window(a);
+        wm.manage_window(b);
+        let parent = viewport_of(&wm, a).unwrap();
+        wm.manage_window(dialog);
+        assert_eq!(wm.windows.in_group(0), vec![a, b, dialog]);
+        assert_eq!(wm.focused_window(), Some(dialog));
+        let vp = viewport_of(&wm, dialog).unwrap();
+        assert_eq!(
+            (vp.x + vp.width / 2, vp.y + vp.height / 2),
+            (parent.x + parent.width / 2, parent.y + parent.height / 2)
+        );
+
+        wm.focus_window(&b);
+        assert_eq!(wm.focused_window(), Some(b));
+        wm.focus_window(&a);
+        assert_eq!(wm.focused_window(), Some(dialog));
+
+        wm.unmanage_window(&dialog);
+        assert_eq!(wm.focused_window(), Some(a));
+    }
+
+    #[test]
+    fn dialogs_of_hidden_windows_go_to_their_group() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let dialog = wm.connection.add_window(
+            11,
+            FakeWindow {
+                transient_for: Some(a),
+                ..Default::default()
+            },
+        );
+        wm.manage_window(a);
+        wm.next_group();
+        wm.manage_window(dialog);
+        assert_eq!(wm.windows.in_group(0), vec![a, dialog]);
+        assert!(!wm.connection.is_mapped(&dialog));
+        assert_eq!(wm.groups[0].focused_window, Some(dialog));
+    }
+
+    #[test]
+    fn focus_urgent_jumps_to_the_window_wanting_attention() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.next_group();
+        wm.manage_window(b);
+
+        wm.connection.update_window(&a, |w| w.urgent = true);
+        wm.on_hints_changed(&a);
+        // The focused window can't ask for more attention.
+        wm.on_state_request(&b, StateAction::Add, &[WindowState::DemandsAttention]);
+        let tree = wm.tree();
+        assert!(tree.groups[0].urgent);
+        assert!(!tree.groups[1].urgent);
+        assert_eq!(
+            wm.connection.get_window_states(&a),
+            vec![WindowState::DemandsAttention]
+        );
+
+        wm.focus_urgent();
+        assert_eq!(wm.group_idx(), Some(0));
+        assert_eq!(wm.focused_window(), Some(a));
+        assert!(wm.urgent.is_empty());
+        assert!(wm.connection.get_window_states(&a).is_empty());
+    }
+
+    #[test]
+    fn fullscreen_requests_cover_the_whole_crtc() {
+        let mut wm = lanta(vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1280, 1024)),
+        ]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(a);
+        wm.manage_window(b);
+        let crtc = wm.current_crtc.unwrap();
+        let full = Viewport::clone_from_crtc_info(&wm.crtc[&crtc].0);
+
+        wm.on_state_request(&a, StateAction::Toggle, &[WindowState::Fullscreen]);
+        assert_eq!(viewport_of(&wm, a), Some(full));
+        assert_eq!(wm.windows.tiled_in_group(wm.group_idx().unwrap()), vec![b]);
+        let requests = wm.connection.take_requests();
+        assert!(requests.contains(&Request::SetStates(a, vec![WindowState::Fullscreen])));
+        assert!(requests.contains(&Request::Raise(a)));
+
+        wm.on_state_request(&a, StateAction::Remove, &[WindowState::Fullscreen]);
+        assert_ne!(viewport_of(&wm, a), Some(full));
+        assert!(wm.connection.get_window_states(&a).is_empty());
+    }
+
+    #[test]
+    fn windows_may_start_out_fullscreen() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(
+            10,
+            FakeWindow {
+                states: vec![WindowState::Fullscreen],
+                ..Default::default()
+            },
+        );
+        wm.manage_window(a);
+        assert_eq!(
+            viewport_of(&wm, a),
+            Some(Viewport::clone_from_crtc_info(&crtc_info(0, 1920, 1080)))
+        );
+    }
+
+    #[test]
+    fn rules_place_float_and_ignore_windows() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            "
+- match: {class: Chat}
+  actions: [{Group: c}, NoFocus]
+- match: {role: pinentry}
+  actions: [Float]
+- match: {instance: osd}
+  actions: [Ignore]
+",
+        )
+        .unwrap();
+        let conn = FakeConnection::new(vec![(1, crtc_info(0, 1920, 1080))]);
+        let mut wm = lanta_from(conn, rules, None);
+        let chat = wm.connection.add_window(
+            10,
+            FakeWindow {
+                class: Some(("chat".to_owned(), "Chat".to_owned())),
+                ..Default::default()
+            },
+        );
+        let prompt = wm.connection.add_window(
+            11,
+            FakeWindow {
+                role: Some("pinentry".to_owned()),
+                ..Default::default()
+            },
+        );
+        let osd = wm.connection.add_window(
+            12,
+            FakeWindow {
+                class: Some(("osd".to_owned(), "Osd".to_owned())),
+                ..Default::default()
+            },
+        );
+        wm.manage_window(chat);
+        wm.manage_window(prompt);
+        wm.manage_window(osd);
+        assert_eq!(wm.windows.in_group(2), vec![chat]);
+        assert_eq!(wm.groups[2].focused_window, None);
+        assert_eq!(wm.windows.in_group(0), vec![prompt]);
+        assert_eq!(wm.windows.tiled_in_group(0), vec![]);
+        assert!(!wm.is_window_managed(&osd));
+        assert!(wm.connection.is_mapped(&osd));
+    }
+
+    #[test]
+    fn crtc_change_allocates_and_releases_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        wm.on_crtc_change(&CrtcChange {
+            timestamp: 0,
+            window: 0,
+            crtc: 2,
+            mode: 0,
+            rotation: 0,
+            x: 1920,
+            y: 0,
+            width: 1280,
+            height: 1024,
+        });
+        assert_eq!(wm.crtc[&2].1, 1);
+        wm.current_crtc = Some(2);
+        wm.on_crtc_change(&CrtcChange {
+            timestamp: 0,
+            window: 0,
+            crtc: 2,
+            mode: 0,
+            rotation: 0,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+        assert!(!wm.crtc.contains_key(&2));
+        assert_eq!(wm.current_crtc, Some(1));
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lanta-{}-{}.sock", name, std::process::id()))
+    }
+
+    fn ipc_roundtrip(wm: &mut Lanta<FakeConnection>, path: &PathBuf, request: &str) -> String {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.write_all(b"\n").unwrap();
+        wm.on_ipc_readable();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        reply
+    }
+
+    #[test]
+    fn ipc_commands_are_parsed_and_run() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let path = socket_path("commands");
+        wm.listen(path.clone(), |text| match text {
+            "GroupNext" => Ok(cmd::lazy::next_group()),
+            _ => Err(format!("Unknown command {}", text).into()),
+        })
+        .unwrap();
+        let reply = ipc_roundtrip(&mut wm, &path, r#"{"Command":"GroupNext"}"#);
+        assert_eq!(reply, "\"Ok\"\n");
+        assert_eq!(wm.group_idx(), Some(1));
+        let reply = ipc_roundtrip(&mut wm, &path, r#"{"Command":"Bogus"}"#);
+        assert_eq!(reply, "{\"Error\":\"Unknown command Bogus\"}\n");
+        drop(wm);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn tree_describes_groups_windows_and_crtcs() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        wm.manage_window(a);
+        let path = socket_path("tree");
+        wm.listen(path.clone(), |_| Err("No commands".into()))
+            .unwrap();
+        let reply = ipc_roundtrip(&mut wm, &path, r#""GetTree""#);
+        let tree = match serde_json::from_str(&reply).unwrap() {
+            Reply::Tree(tree) => tree,
+            other => panic!("Expected a tree, got {:?}", other),
+        };
+        assert_eq!(tree.groups.len(), 3);
+        assert_eq!(tree.groups[0].layout, "3-column");
+        assert_eq!(tree.groups[0].focused_window, Some(a));
+        assert_eq!(tree.windows.len(), 1);
+        assert_eq!(tree.windows[0].group, 0);
+        assert_eq!(tree.crtcs[0].group, 0);
+        assert_eq!(tree.current_crtc, Some(1));
+        assert_eq!(tree.mapped[0].id, a);
+        assert!(tree.docks.is_empty());
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_changes() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let path = socket_path("subscribe");
+        wm.listen(path.clone(), |_| Err("No commands".into()))
+            .unwrap();
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"\"Subscribe\"\n").unwrap();
+        wm.on_ipc_readable();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "\"Ok\"");
+
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        wm.manage_window(a);
+        wm.next_group();
+        wm.group_cycle_layouts();
+        let mut next = || serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        match next() {
+            Notification::WindowManaged { id, group: 0 } => assert_eq!(id, a),
+            other => panic!("Unexpected {:?}", other),
+        }
+        match next() {
+            Notification::WindowFocused { id } => assert_eq!(id, a),
+            other => panic!("Unexpected {:?}", other),
+        }
+        match next() {
+            Notification::GroupFocused {
+                crtc: Some(1),
+                group: 1,
+            } => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+        match next() {
+            Notification::LayoutChanged { group: 1, layout } => assert_eq!(layout, "stack"),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        wm.prev_group();
+        let b = wm.connection.add_window(11, FakeWindow::default());
+        wm.manage_window(b);
+        wm.unmanage_window(&a);
+        let mut next = || serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert!(matches!(
+            next(),
+            Notification::GroupFocused { group: 0, .. }
+        ));
+        match next() {
+            Notification::WindowFocused { id } => assert_eq!(id, a),
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(matches!(next(), Notification::WindowManaged { .. }));
+        assert!(matches!(next(), Notification::WindowUnmanaged { .. }));
+        match next() {
+            Notification::WindowFocused { id } => assert_eq!(id, b),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    fn config(groups: &[&str]) -> Config<FakeConnection> {
+        let layouts: Vec<Box<dyn Layout<WindowId>>> = vec![
+            Box::new(StackLayout::new("stack", 0)),
+            Box::new(ThreeColumn::new("3-column", 0)),
+        ];
+        let keys = vec![(vec![ModKey::Mod1], keysym::XK_n, cmd::lazy::next_group())];
+        Config {
+            keys: keys.into(),
+            groups: groups
+                .iter()
+                .map(|&name| Group::new(name.to_owned(), "stack", &layouts))
+                .collect(),
+            layouts,
+            rules: Vec::new(),
+            close_timeout: None,
+            group_policy: GroupPolicy::default(),
+            back_and_forth: false,
+        }
+    }
+
+    #[test]
+    fn set_layout_and_prev_layout_change_the_current_group() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let layout =
+            |wm: &Lanta<FakeConnection>| wm.layouts[wm.groups[0].layout_id].name().to_owned();
+        wm.set_layout("stack").unwrap();
+        assert_eq!(layout(&wm), "stack");
+        assert!(wm.set_layout("spiral").is_err());
+        wm.group_prev_layout();
+        assert_eq!(layout(&wm), "3-column");
+
+        wm.groups[0].layout_cycle = vec![0];
+        wm.group_cycle_layouts();
+        assert_eq!(layout(&wm), "3-column");
+    }
+
+    #[test]
+    fn reconfigure_keeps_windows_with_their_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        let c = wm.connection.add_window(12, FakeWindow::default());
+        wm.manage_window(a);
+        wm.focus_group(2);
+        wm.manage_window(c);
+        wm.group_cycle_layouts();
+        wm.connection.take_requests();
+
+        wm.reconfigure(config(&["new", "a", "c"])).unwrap();
+        assert_eq!(wm.windows.in_group(1), vec![a]);
+        assert_eq!(wm.windows.in_group(2), vec![c]);
+        assert_eq!(wm.groups[1].focused_window, Some(a));
+        assert_eq!(wm.group_idx(), Some(2));
+        // Group "c" had switched to "stack" at runtime, group "a" is still
+        // on the "3-column" layout it started with.
+        assert_eq!(wm.layouts[wm.groups[1].layout_id].name(), "3-column");
+        assert_eq!(wm.layouts[wm.groups[2].layout_id].name(), "stack");
+
+        let requests = wm.connection.take_requests();
+        let new_keys = vec![KeyCombo {
+            mod_mask: xcb::MOD_MASK_1,
+            keysym: keysym::XK_n,
+        }];
+        for window in &[*wm.connection.root_window_id(), a, c] {
+            let ungrab = requests
+                .iter()
+                .position(|r| r == &Request::UngrabKeys(*window));
+            let grab = requests
+                .iter()
+                .position(|r| r == &Request::GrabKeys(*window, new_keys.clone()));
+            assert!(ungrab.is_some() && ungrab < grab);
+        }
+    }
+
+    #[test]
+    fn reconfigure_moves_windows_out_of_removed_groups() {
+        let mut wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let c = wm.connection.add_window(12, FakeWindow::default());
+        wm.focus_group(2);
+        wm.manage_window(c);
+        wm.reconfigure(config(&["a", "b"])).unwrap();
+        assert_eq!(wm.windows.in_group(1), vec![c]);
+        assert_eq!(wm.groups[1].focused_window, Some(c));
+        assert_eq!(wm.group_idx(), Some(1));
+    }
+
+    #[test]
+    fn reconfigure_rejects_too_few_groups() {
+        let mut wm = lanta(vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1920, 1080)),
+        ]);
+        assert!(wm.reconfigure(config(&["a"])).is_err());
+        assert_eq!(wm.groups.len(), 3);
+    }
+
+    #[test]
+    fn restart_restores_window_placement() {
+        let crtcs = vec![
+            (1, crtc_info(0, 1920, 1080)),
+            (2, crtc_info(1920, 1920, 1080)),
+        ];
+        let conn = FakeConnection::new(crtcs.clone());
+        conn.add_top_level_window(10, FakeWindow::default());
+        conn.add_top_level_window(11, FakeWindow::default());
+        let mut wm = lanta_with(conn);
+        wm.focus_group(2);
+        let c = wm.connection.add_window(12, FakeWindow::default());
+        wm.manage_window(c);
+        wm.group_cycle_layouts();
+        wm.rotate_crtc();
+        let saved = serde_json::to_string(&wm.saved_state()).unwrap();
+
+        // The restarted window manager also finds a window that appeared in
+        // the meantime.
+        let conn = FakeConnection::new(crtcs);
+        for id in &[10, 11, 12] {
+            conn.add_top_level_window(*id, FakeWindow::default());
+        }
+        let d = conn.add_top_level_window(13, FakeWindow::default());
+        let wm2 = lanta_from(
+            conn,
+            Vec::new(),
+            Some(serde_json::from_str(&saved).unwrap()),
+        );
+        let d_group = wm2.windows.iter().find(|w| w.id == d).unwrap().group;
+        for gid in 0..3 {
+            let mut windows = wm2.windows.in_group(gid);
+            windows.retain(|&w| w != d);
+            assert_eq!(windows, wm.windows.in_group(gid));
+            assert_eq!(wm2.groups[gid].layout_id, wm.groups[gid].layout_id);
+            if gid != d_group {
+                assert_eq!(
+                    wm2.groups[gid].focused_window,
+                    wm.groups[gid].focused_window
+                );
+            }
+        }
+        assert_eq!(wm2.crtc[&1].1, wm.crtc[&1].1);
+        assert_eq!(wm2.crtc[&2].1, wm.crtc[&2].1);
+        assert_eq!(wm2.current_crtc, wm.current_crtc);
+    }
+
+    #[test]
+    fn restart_keeps_groups_changed_at_runtime() {
+        let crtcs = vec![(1, crtc_info(0, 1920, 1080))];
+        let mut wm = lanta(crtcs.clone());
+        wm.add_group("scratch", None).unwrap();
+        wm.go_to_group(&GroupSelector::Name("scratch".to_owned()))
+            .unwrap();
+        let a = wm.connection.add_window(10, FakeWindow::default());
+        wm.manage_window(a);
+        wm.remove_group(&GroupSelector::Index(0)).unwrap();
+        let saved = serde_json::to_string(&wm.saved_state()).unwrap();
+
+        let conn = FakeConnection::new(crtcs);
+        conn.add_top_level_window(10, FakeWindow::default());
+        let wm2 = lanta_from(
+            conn,
+            Vec::new(),
+            Some(serde_json::from_str(&saved).unwrap()),
+        );
+        let names: Vec<&str> = wm2.groups.iter().map(|g| g.name()).collect();
+        // Group "a" is still in the configuration.
+        assert_eq!(names, vec!["b", "c", "scratch", "a"]);
+        assert_eq!(wm2.windows.in_group(2), vec![a]);
+        assert_eq!(wm2.group_idx(), Some(2));
+    }
+
+    #[test]
+    fn run_dispatches_injected_events() {
+        let wm = lanta(vec![(1, crtc_info(0, 1920, 1080))]);
+        let conn = wm.connection.clone();
+        let a = conn.add_window(10, FakeWindow::default());
+        conn.inject(Event::MapRequest(a));
+        conn.inject(Event::DestroyNotify(a));
+        wm.run();
+        let requests = conn.take_requests();
+        assert!(requests.contains(&Request::Map(a)));
+        assert_eq!(requests.last(), Some(&Request::Focus(None)));
+    }
+}
@@ -0,0 +1,120 @@
+//! Saving the placement of windows across a restart of the window manager.
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use crate::viewport::Viewport;
+use crate::x::{Crtc, WindowId};
+use crate::Result;
+
+/// Tells the restarted window manager where to find its saved state.
+const STATE_VAR: &str = "LANTA_RESTART_STATE";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedWindow {
+    pub id: WindowId,
+    pub group: usize,
+    #[serde(default)]
+    pub floating: Option<Viewport>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedGroup {
+    /// Missing from the state saved by versions that could not change
+    /// groups at runtime.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub layout_id: usize,
+    pub focused_window: Option<WindowId>,
+}
+
+/// Everything needed to put windows back where they were.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedState {
+    /// Every managed window, in stacking order.
+    pub windows: Vec<SavedWindow>,
+    pub groups: Vec<SavedGroup>,
+    pub crtc: Vec<(Crtc, usize)>,
+    pub current_crtc: Option<Crtc>,
+}
+
+fn state_path() -> Result<PathBuf> {
+    let dirs = xdg::BaseDirectories::with_prefix("lanta")?;
+    Ok(dirs.place_runtime_file("restart.json")?)
+}
+
+/// Writes `state` to disk and replaces the running process with a fresh copy
+/// of the same executable, which will pick it up with `take_saved_state()`.
+///
+/// Only returns if the restart failed.
+pub fn exec_with_state(state: &SavedState) -> Result<()> {
+    let path = state_path()?;
+    serde_json::to_writer(File::create(&path)?, state)?;
+    let exe = executable_path(env::current_exe()?);
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    info!(
+        "Restarting {} with state from {}",
+        exe.display(),
+        path.display()
+    );
+    let error = process::Command::new(exe)
+        .args(args)
+        .env(STATE_VAR, &path)
+        .exec();
+    let _ = fs::remove_file(&path);
+    Err(error.into())
+}
+
+/// Returns the path to exec to run the current executable again.
+///
+/// Once the executable has been replaced, as when Lanta is rebuilt or
+/// upgraded, Linux reports the path of the running one with a ` (deleted)`
+/// suffix. The new executable is at the original path.
+fn executable_path(exe: PathBuf) -> PathBuf {
+    match exe.to_str().and_then(|s| s.strip_suffix(" (deleted)")) {
+        Some(path) => PathBuf::from(path),
+        None => exe,
+    }
+}
+
+/// Returns the state saved by the process that restarted into this one, if
+/// there was one.
+pub fn take_saved_state() -> Option<SavedState> {
+    let path = env::var_os(STATE_VAR)?;
+    env::remove_var(STATE_VAR);
+    match load(Path::new(&path)) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            error!("Could not restore state from {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<SavedState> {
+    let state = serde_json::from_reader(File::open(path)?)?;
+    fs::remove_file(path)?;
+    Ok(state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replaced_executable_is_run_from_its_original_path() {
+        assert_eq!(
+            executable_path(PathBuf::from("/usr/bin/lanta (deleted)")),
+            PathBuf::from("/usr/bin/lanta")
+        );
+        assert_eq!(
+            executable_path(PathBuf::from("/usr/bin/lanta")),
+            PathBuf::from("/usr/bin/lanta")
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Rules that decide how a window is managed, based on its properties.
+use serde::Deserialize;
+
+use crate::x::{WindowId, WindowType, XConnection};
+
+/// The properties of a window that rules can match on.
+#[derive(Debug, Default)]
+pub struct WindowProperties {
+    pub instance: Option<String>,
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub role: Option<String>,
+    pub types: Vec<WindowType>,
+}
+
+impl WindowProperties {
+    pub fn of<C: XConnection>(connection: &C, window_id: &WindowId) -> WindowProperties {
+        let (instance, class) = match connection.get_wm_class(window_id) {
+            Some((instance, class)) => (Some(instance), Some(class)),
+            None => (None, None),
+        };
+        WindowProperties {
+            instance,
+            class,
+            title: connection.get_window_title(window_id),
+            role: connection.get_window_role(window_id),
+            types: connection.get_window_types(window_id),
+        }
+    }
+}
+
+/// Selects windows by their properties.
+///
+/// Every property that is given must be equal to that of the window; a match
+/// with no properties matches every window.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Match {
+    pub instance: Option<String>,
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub role: Option<String>,
+    #[serde(rename = "type")]
+    pub window_type: Option<WindowType>,
+}
+
+impl Match {
+    pub fn matches(&self, window: &WindowProperties) -> bool {
+        fn equal(expected: &Option<String>, actual: &Option<String>) -> bool {
+            expected.is_none() || expected == actual
+        }
+        equal(&self.instance, &window.instance)
+            && equal(&self.class, &window.class)
+            && equal(&self.title, &window.title)
+            && equal(&self.role, &window.role)
+            && self
+                .window_type
+                .filter(|t| !window.types.contains(t))
+                .is_none()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum Action {
+    /// Put the window in the named group, rather than the active one.
+    Group(String),
+    Float,
+    /// Leave the window alone, mapping it without managing it.
+    Ignore,
+    /// Never give the window focus when it is mapped.
+    NoFocus,
+    Fullscreen,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rule {
+    #[serde(rename = "match")]
+    pub matches: Match,
+    pub actions: Vec<Action>,
+}
+
+/// Returns the actions of every rule that matches `window`, in order.
+pub fn actions_for<'a>(
+    rules: &'a [Rule],
+    window: &'a WindowProperties,
+) -> impl Iterator<Item = &'a Action> + 'a {
+    rules
+        .iter()
+        .filter(move |rule| rule.matches.matches(window))
+        .flat_map(|rule| rule.actions.iter())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn firefox() -> WindowProperties {
+        WindowProperties {
+            instance: Some("Navigator".to_owned()),
+            class: Some("Firefox".to_owned()),
+            title: Some("Mozilla Firefox".to_owned()),
+            role: Some("browser".to_owned()),
+            types: vec![WindowType::Normal],
+        }
+    }
+
+    #[test]
+    fn every_given_property_must_match() {
+        let window = firefox();
+        assert!(Match::default().matches(&window));
+        let class = Match {
+            class: Some("Firefox".to_owned()),
+            ..Default::default()
+        };
+        assert!(class.matches(&window));
+        let class_and_role = Match {
+            role: Some("dialog".to_owned()),
+            ..class.clone()
+        };
+        assert!(!class_and_role.matches(&window));
+        let dialog = Match {
+            window_type: Some(WindowType::Dialog),
+            ..class
+        };
+        assert!(!dialog.matches(&window));
+        assert!(!dialog.matches(&WindowProperties::default()));
+    }
+
+    #[test]
+    fn rules_are_read_from_yaml() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            "
+- match: {class: Firefox, type: Normal}
+  actions: [{Group: web}, NoFocus]
+- match: {role: pinentry}
+  actions: [Float]
+",
+        )
+        .unwrap();
+        let window = firefox();
+        let actions: Vec<_> = actions_for(&rules, &window).cloned().collect();
+        assert_eq!(
+            actions,
+            vec![Action::Group("web".to_owned()), Action::NoFocus]
+        );
+    }
+}
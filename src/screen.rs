@@ -1,11 +1,21 @@
 use std::cmp;
 
 use crate::viewport::{Strut, Viewport};
-use crate::x::{Connection, StrutPartial, WindowId};
+use crate::x::{WindowId, XConnection};
 
 pub struct Dock {
     window_id: WindowId,
-    strut_partial: Option<StrutPartial>,
+    strut: Option<Strut>,
+}
+
+impl Dock {
+    pub fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    pub fn strut(&self) -> Option<&Strut> {
+        self.strut.as_ref()
+    }
 }
 
 pub trait Dockable {
@@ -14,7 +24,7 @@ pub trait Dockable {
 
 impl Dockable for Dock {
     fn get_strut(&self) -> Option<Strut> {
-        self.strut_partial.as_ref().map(Strut::from_strut_partial)
+        self.strut.clone()
     }
 }
 
@@ -29,16 +39,16 @@ impl<T> Default for Screen<T> {
 }
 
 impl Screen<Dock> {
-    pub fn add_dock(&mut self, conn: &Connection, window_id: WindowId) {
-        let strut_partial = conn.get_strut_partial(&window_id);
-        self.add(Dock {
-            window_id,
-            strut_partial,
-        });
+    pub fn add_dock<C: XConnection>(&mut self, conn: &C, window_id: WindowId) {
+        let strut = conn.get_strut(&window_id);
+        self.add(Dock { window_id, strut });
     }
 
-    pub fn remove_dock(&mut self, window_id: &WindowId) {
+    /// Returns whether `window_id` was a dock.
+    pub fn remove_dock(&mut self, window_id: &WindowId) -> bool {
+        let before = self.docks.len();
         self.docks.retain(|d| &d.window_id != window_id);
+        self.docks.len() != before
     }
 }
 
@@ -46,6 +56,10 @@ impl<T: Dockable> Screen<T> {
     pub fn add(&mut self, dock: T) {
         self.docks.push(dock)
     }
+
+    pub fn docks(&self) -> &[T] {
+        &self.docks
+    }
     /// Figure out the usable area of the screen based on the STRUT_PARTIAL of
     /// all docks.
     pub fn viewports(&self, mut ports: Vec<Viewport>) -> Vec<Viewport> {
@@ -0,0 +1,75 @@
+//! Delivers SIGHUP to the event loop by way of a self-pipe.
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The write end of the pipe, for use by the signal handler.
+static HANGUP_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_hangup(_signal: c_int) {
+    let fd = HANGUP_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // Nothing useful can be done if this fails: either the pipe is full,
+        // and a reload is already pending, or the pipe is gone.
+        unsafe {
+            libc::write(fd, b"!".as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// A file descriptor that becomes readable whenever the process receives
+/// SIGHUP.
+pub struct Hangup {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Hangup {
+    pub fn install() -> io::Result<Hangup> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let hangup = Hangup {
+            read: fds[0],
+            write: fds[1],
+        };
+        HANGUP_FD.store(hangup.write, Ordering::Relaxed);
+        let handler = on_hangup as extern "C" fn(c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(libc::SIGHUP, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(hangup)
+    }
+
+    /// Consumes any pending signals, so that the file descriptor is no longer
+    /// readable.
+    pub fn drain(&self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let read =
+                unsafe { libc::read(self.read, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if read <= 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl AsRawFd for Hangup {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read
+    }
+}
+
+impl Drop for Hangup {
+    fn drop(&mut self) {
+        HANGUP_FD.store(-1, Ordering::Relaxed);
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_DFL);
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! A file descriptor that becomes readable when a deadline passes, so that
+//! the event loop can wait on it alongside the X connection.
+use std::cmp;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+
+pub struct Timer {
+    fd: RawFd,
+}
+
+impl Timer {
+    pub fn new() -> io::Result<Timer> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Timer { fd })
+    }
+
+    /// Makes the timer fire once, `after` from now, replacing any earlier
+    /// setting. `None` stops the timer.
+    pub fn set(&self, after: Option<Duration>) {
+        // A zero value would disarm the timer instead of firing at once.
+        let after = after.map(|d| cmp::max(d, Duration::from_nanos(1)));
+        let value = after.map_or(
+            libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            |d| libc::timespec {
+                tv_sec: d.as_secs() as libc::time_t,
+                tv_nsec: d.subsec_nanos() as libc::c_long,
+            },
+        );
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: value,
+        };
+        if unsafe { libc::timerfd_settime(self.fd, 0, &spec, ptr::null_mut()) } != 0 {
+            error!("Could not set timer: {}", io::Error::last_os_error());
+        }
+    }
+
+    /// Acknowledges the timer firing, so that the file descriptor is no
+    /// longer readable.
+    pub fn drain(&self) {
+        let mut expirations = 0u64;
+        unsafe {
+            libc::read(
+                self.fd,
+                &mut expirations as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use std::cmp;
 
+use serde::{Deserialize, Serialize};
+
 use crate::x::{CrtcInfo, StrutPartial};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Strut {
     pub left: u32,
     pub right: u32,
@@ -37,7 +39,143 @@ impl Strut {
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+/// The size constraints a window asks for in its `WM_NORMAL_HINTS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SizeHints {
+    pub min: Option<(u32, u32)>,
+    pub max: Option<(u32, u32)>,
+    pub base: Option<(u32, u32)>,
+    pub increment: Option<(u32, u32)>,
+    /// The smallest and largest width to height ratios, each as a numerator
+    /// and denominator.
+    pub aspect: Option<((u32, u32), (u32, u32))>,
+    /// One of the `xcb::GRAVITY_*` constants.
+    pub gravity: Option<u32>,
+}
+
+impl SizeHints {
+    const P_MIN_SIZE: u32 = 1 << 4;
+    const P_MAX_SIZE: u32 = 1 << 5;
+    const P_RESIZE_INC: u32 = 1 << 6;
+    const P_ASPECT: u32 = 1 << 7;
+    const P_BASE_SIZE: u32 = 1 << 8;
+    const P_WIN_GRAVITY: u32 = 1 << 9;
+
+    /// Reads the raw `WM_SIZE_HINTS` property, as laid out by ICCCM 4.1.2.3.
+    pub fn from_wm_size_hints(values: &[u32]) -> SizeHints {
+        let flags = values.first().cloned().unwrap_or(0);
+        // The fields are signed, and anything negative is nonsense.
+        let pair = |flag: u32, index: usize| {
+            if flags & flag == 0 {
+                return None;
+            }
+            match (values.get(index), values.get(index + 1)) {
+                (Some(&a), Some(&b)) if (a as i32) >= 0 && (b as i32) >= 0 => Some((a, b)),
+                _ => None,
+            }
+        };
+        // A ratio of zero would squash the window to nothing.
+        let ratio = |index: usize| pair(Self::P_ASPECT, index).filter(|&(n, d)| n > 0 && d > 0);
+        let aspect = match (ratio(11), ratio(13)) {
+            (Some(min), Some(max)) => Some((min, max)),
+            _ => None,
+        };
+        SizeHints {
+            min: pair(Self::P_MIN_SIZE, 5),
+            max: pair(Self::P_MAX_SIZE, 7),
+            increment: pair(Self::P_RESIZE_INC, 9),
+            aspect,
+            base: pair(Self::P_BASE_SIZE, 15),
+            gravity: values
+                .get(17)
+                .cloned()
+                .filter(|_| flags & Self::P_WIN_GRAVITY != 0),
+        }
+    }
+
+    /// Whether the window cannot be resized at all.
+    pub fn is_fixed(&self) -> bool {
+        self.min.is_some() && self.min == self.max
+    }
+
+    /// Returns the largest viewport within `slot` that the window will accept,
+    /// placed in the slot according to the window's gravity.
+    ///
+    /// A minimum size that does not fit in the slot is ignored, as is an
+    /// aspect ratio or increment that would make the window smaller than it.
+    pub fn fit(&self, slot: &Viewport) -> Viewport {
+        let (mut width, mut height) = (slot.width, slot.height);
+        if let Some((max_width, max_height)) = self.max {
+            width = cmp::min(width, cmp::max(max_width, 1));
+            height = cmp::min(height, cmp::max(max_height, 1));
+        }
+        // ICCCM says that the base size falls back to the minimum size, and
+        // vice versa.
+        let base = self.base.or(self.min).unwrap_or((0, 0));
+        let (least_width, least_height) = match self.min.or(self.base) {
+            Some((min_width, min_height)) => (cmp::max(min_width, 1), cmp::max(min_height, 1)),
+            None => (1, 1),
+        };
+        if let Some(((min_x, min_y), (max_x, max_y))) = self.aspect {
+            // The ratio applies to the size beyond the base size, if given.
+            let (base_width, base_height) = self.base.unwrap_or((0, 0));
+            let w = u64::from(width.saturating_sub(base_width));
+            let h = u64::from(height.saturating_sub(base_height));
+            if w * u64::from(max_y) > h * u64::from(max_x) {
+                let narrower = (h * u64::from(max_x) / u64::from(max_y)) as u32 + base_width;
+                if narrower >= least_width {
+                    width = narrower;
+                }
+            } else if min_x > 0 && w * u64::from(min_y) < h * u64::from(min_x) {
+                let shorter = (w * u64::from(min_y) / u64::from(min_x)) as u32 + base_height;
+                if shorter >= least_height {
+                    height = shorter;
+                }
+            }
+        }
+        if let Some((inc_width, inc_height)) = self.increment {
+            if inc_width > 0 && width > base.0 {
+                let snapped = width - (width - base.0) % inc_width;
+                if snapped >= least_width {
+                    width = snapped;
+                }
+            }
+            if inc_height > 0 && height > base.1 {
+                let snapped = height - (height - base.1) % inc_height;
+                if snapped >= least_height {
+                    height = snapped;
+                }
+            }
+        }
+        if let Some((min_width, min_height)) = self.min.or(self.base) {
+            width = cmp::min(cmp::max(width, min_width), slot.width);
+            height = cmp::min(cmp::max(height, min_height), slot.height);
+        }
+        // X rejects windows with no width or height.
+        let (width, height) = (cmp::max(width, 1), cmp::max(height, 1));
+        let spare_x = slot.width.saturating_sub(width);
+        let spare_y = slot.height.saturating_sub(height);
+        let (x, y) = match self.gravity {
+            Some(xcb::GRAVITY_NORTH_WEST) => (0, 0),
+            Some(xcb::GRAVITY_NORTH) => (spare_x / 2, 0),
+            Some(xcb::GRAVITY_NORTH_EAST) => (spare_x, 0),
+            Some(xcb::GRAVITY_WEST) => (0, spare_y / 2),
+            Some(xcb::GRAVITY_EAST) => (spare_x, spare_y / 2),
+            Some(xcb::GRAVITY_SOUTH_WEST) => (0, spare_y),
+            Some(xcb::GRAVITY_SOUTH) => (spare_x / 2, spare_y),
+            Some(xcb::GRAVITY_SOUTH_EAST) => (spare_x, spare_y),
+            _ => (spare_x / 2, spare_y / 2),
+        };
+        Viewport {
+            x: slot.x + x,
+            y: slot.y + y,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Viewport {
     pub x: u32,
     pub y: u32,
@@ -179,3 +317,139 @@ fn bottom_strut_outside_does_not_change() {
         }
     )
 }
+
+#[test]
+fn size_hints_are_read_from_the_raw_property() {
+    let mut values = vec![0; 18];
+    values[0] = (1 << 4) | (1 << 6) | (1 << 8);
+    values[5..11].copy_from_slice(&[20, 30, 0, 0, 8, 16]);
+    values[15..17].copy_from_slice(&[4, 6]);
+    assert_eq!(
+        SizeHints::from_wm_size_hints(&values),
+        SizeHints {
+            min: Some((20, 30)),
+            base: Some((4, 6)),
+            increment: Some((8, 16)),
+            ..Default::default()
+        }
+    );
+    // Older clients only write the first 15 fields.
+    assert_eq!(SizeHints::from_wm_size_hints(&values[..15]).base, None);
+}
+
+#[test]
+fn fit_snaps_to_increments_and_centers() {
+    let slot = Viewport {
+        x: 100,
+        y: 0,
+        width: 805,
+        height: 600,
+    };
+    let terminal = SizeHints {
+        base: Some((4, 4)),
+        increment: Some((10, 20)),
+        ..Default::default()
+    };
+    assert_eq!(
+        terminal.fit(&slot),
+        Viewport {
+            x: 100,
+            y: 8,
+            width: 804,
+            height: 584,
+        }
+    );
+    assert_eq!(SizeHints::default().fit(&slot), slot);
+}
+
+#[test]
+fn fit_respects_max_size_aspect_and_gravity() {
+    let slot = Viewport {
+        x: 0,
+        y: 0,
+        width: 1000,
+        height: 500,
+    };
+    let capped = SizeHints {
+        max: Some((400, 800)),
+        gravity: Some(xcb::GRAVITY_SOUTH_EAST),
+        ..Default::default()
+    };
+    assert_eq!(
+        capped.fit(&slot),
+        Viewport {
+            x: 600,
+            y: 0,
+            width: 400,
+            height: 500,
+        }
+    );
+    let square = SizeHints {
+        aspect: Some(((1, 1), (1, 1))),
+        ..Default::default()
+    };
+    assert_eq!(
+        square.fit(&slot),
+        Viewport {
+            x: 250,
+            y: 0,
+            width: 500,
+            height: 500,
+        }
+    );
+    let too_big = SizeHints {
+        min: Some((2000, 100)),
+        ..Default::default()
+    };
+    assert_eq!(too_big.fit(&slot), slot);
+}
+
+#[test]
+fn fit_skips_steps_that_would_empty_the_window() {
+    let slot = Viewport {
+        x: 0,
+        y: 0,
+        width: 100,
+        height: 50,
+    };
+    let flat = SizeHints {
+        aspect: Some(((0, 1), (0, 1))),
+        ..Default::default()
+    };
+    assert_eq!(flat.fit(&slot), slot);
+    let coarse = SizeHints {
+        increment: Some((300, 300)),
+        ..Default::default()
+    };
+    assert_eq!(coarse.fit(&slot), slot);
+
+    // Such an aspect ratio isn't even read from the property.
+    let mut values = vec![0; 15];
+    values[0] = 1 << 7;
+    values[11..15].copy_from_slice(&[0, 1, 0, 1]);
+    assert_eq!(SizeHints::from_wm_size_hints(&values).aspect, None);
+}
+
+#[test]
+fn fit_applies_aspect_beyond_the_base_size() {
+    let slot = Viewport {
+        x: 0,
+        y: 0,
+        width: 110,
+        height: 60,
+    };
+    let square = SizeHints {
+        base: Some((10, 10)),
+        aspect: Some(((1, 1), (1, 1))),
+        ..Default::default()
+    };
+    assert_eq!(
+        square.fit(&slot),
+        Viewport {
+            x: 25,
+            y: 0,
+            width: 60,
+            height: 60,
+        }
+    );
+}
@@ -1,20 +1,30 @@
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
 use std::io::{Error, ErrorKind};
+use std::os::unix::io::{AsRawFd, RawFd};
 
+use serde::{Deserialize, Serialize};
 use xcb::randr;
 use xcb_util::keysyms::KeySymbols;
 use xcb_util::{ewmh, icccm};
 
 use crate::groups::Group;
-use crate::keys::{KeyCombo, KeyHandlers};
+use crate::keys::KeyCombo;
+use crate::viewport::{SizeHints, Strut, Viewport};
 use crate::Result;
 
+#[cfg(test)]
+pub mod fake;
+
 pub use self::ewmh::StrutPartial;
 pub use randr::Crtc;
 
+/// The name Lanta gives itself in `_NET_WM_NAME`.
+const WM_NAME: &str = "lanta";
+
 /// A handle to an X Window.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct WindowId(xcb::Window);
 
 impl WindowId {
@@ -29,7 +39,7 @@ impl fmt::Display for WindowId {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum WindowType {
     Desktop,
     Dock,
@@ -63,6 +73,113 @@ pub enum WindowState {
     DemandsAttention,
 }
 
+/// The ICCCM `WM_STATE` of a top-level window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WmState {
+    Withdrawn,
+    Normal,
+    Iconic,
+}
+
+impl WmState {
+    fn from_x(state: u32) -> Option<WmState> {
+        match state {
+            0 => Some(WmState::Withdrawn),
+            1 => Some(WmState::Normal),
+            3 => Some(WmState::Iconic),
+            _ => None,
+        }
+    }
+
+    fn to_x(self) -> u32 {
+        match self {
+            WmState::Withdrawn => 0,
+            WmState::Normal => 1,
+            WmState::Iconic => 3,
+        }
+    }
+}
+
+/// How a client asked for its `_NET_WM_STATE` to change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StateAction {
+    Remove,
+    Add,
+    Toggle,
+}
+
+/// How a window expects to be given keyboard focus, as described in ICCCM
+/// 4.1.7.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum InputModel {
+    /// The window never takes keyboard input.
+    NoInput,
+    /// The window manager sets the focus.
+    #[default]
+    Passive,
+    /// The window manager sets the focus, and tells the client with
+    /// `WM_TAKE_FOCUS` so it can move the focus between its own windows.
+    LocallyActive,
+    /// The client sets the focus itself when sent `WM_TAKE_FOCUS`.
+    GloballyActive,
+}
+
+impl InputModel {
+    fn new(input: bool, take_focus: bool) -> InputModel {
+        match (input, take_focus) {
+            (false, false) => InputModel::NoInput,
+            (true, false) => InputModel::Passive,
+            (true, true) => InputModel::LocallyActive,
+            (false, true) => InputModel::GloballyActive,
+        }
+    }
+
+    /// Whether the window manager should set the input focus to the window.
+    pub fn accepts_input(self) -> bool {
+        self == InputModel::Passive || self == InputModel::LocallyActive
+    }
+
+    /// Whether the window wants to be sent `WM_TAKE_FOCUS`.
+    pub fn takes_focus(self) -> bool {
+        self == InputModel::LocallyActive || self == InputModel::GloballyActive
+    }
+}
+
+/// A client's request to change its own geometry or stacking order.
+///
+/// Only the fields the client asked to change are set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigureRequest {
+    pub window: WindowId,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub border_width: Option<u32>,
+    pub sibling: Option<WindowId>,
+    pub stack_mode: Option<u32>,
+}
+
+impl ConfigureRequest {
+    /// Returns the values to pass to `xcb::configure_window()` to grant the
+    /// request.
+    fn values(&self) -> Vec<(u16, u32)> {
+        let values = [
+            (xcb::CONFIG_WINDOW_X, self.x.map(|x| x as u32)),
+            (xcb::CONFIG_WINDOW_Y, self.y.map(|y| y as u32)),
+            (xcb::CONFIG_WINDOW_WIDTH, self.width),
+            (xcb::CONFIG_WINDOW_HEIGHT, self.height),
+            (xcb::CONFIG_WINDOW_BORDER_WIDTH, self.border_width),
+            (xcb::CONFIG_WINDOW_SIBLING, self.sibling.map(|s| s.to_x())),
+            (xcb::CONFIG_WINDOW_STACK_MODE, self.stack_mode),
+        ];
+        values
+            .iter()
+            .filter_map(|&(mask, value)| value.map(|v| (mask as u16, v)))
+            .collect()
+    }
+}
+
 macro_rules! atoms {
     ( $( $name:ident ),+ ) => {
         #[allow(non_snake_case)]
@@ -86,9 +203,15 @@ macro_rules! atoms {
     ( $( $name:ident ),+ , ) => (atoms!($( $name ),+);)
 }
 
-atoms!(WM_DELETE_WINDOW, WM_PROTOCOLS,);
+atoms!(
+    WM_DELETE_WINDOW,
+    WM_PROTOCOLS,
+    WM_STATE,
+    WM_TAKE_FOCUS,
+    WM_WINDOW_ROLE,
+);
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CrtcInfo {
     pub x: i16,
     pub y: i16,
@@ -118,9 +241,143 @@ impl From<&CrtcChange> for CrtcInfo {
     }
 }
 
+/// The requests Lanta makes of the X server.
+///
+/// `Connection` implements this on top of xcb. Abstracting over it allows the
+/// window management logic in `Lanta` to be driven without an X server.
 pub trait XConnection {
-    fn list_crtc(&self) -> Result<Vec<(randr::Crtc, CrtcInfo)>>;
-    fn install_as_wm(&self, key_handlers: &KeyHandlers) -> Result<()>;
+    /// Lists the CRTCs known to randr, along with their geometry.
+    fn list_crtc(&self) -> Result<Vec<(Crtc, CrtcInfo)>>;
+
+    /// Installs the connection as the window manager of the display, grabbing
+    /// the given key combinations on the root window.
+    fn install_as_wm(&self, key_combos: &[&KeyCombo]) -> Result<()>;
+
+    /// Returns the ID of the root window.
+    fn root_window_id(&self) -> &WindowId;
+
+    /// Lists the children of the root window.
+    fn top_level_windows(&self) -> Result<Vec<WindowId>>;
+
+    /// Returns whether the window has asked not to be managed.
+    fn is_override_redirect(&self, window_id: &WindowId) -> Result<bool>;
+
+    /// Returns whether the window is currently mapped.
+    fn is_viewable(&self, window_id: &WindowId) -> bool;
+
+    /// Returns the window's ICCCM `WM_STATE`, if it has one.
+    fn get_wm_state(&self, window_id: &WindowId) -> Option<WmState>;
+
+    /// Sets the window's ICCCM `WM_STATE`.
+    fn set_wm_state(&self, window_id: &WindowId, state: WmState);
+
+    fn get_window_types(&self, window_id: &WindowId) -> Vec<WindowType>;
+
+    /// Returns the instance and class names from WM_CLASS.
+    fn get_wm_class(&self, window_id: &WindowId) -> Option<(String, String)>;
+
+    /// Returns `_NET_WM_NAME`, falling back to WM_NAME.
+    fn get_window_title(&self, window_id: &WindowId) -> Option<String>;
+
+    /// Returns WM_WINDOW_ROLE.
+    fn get_window_role(&self, window_id: &WindowId) -> Option<String>;
+
+    fn get_window_states(&self, window_id: &WindowId) -> Vec<WindowState>;
+
+    /// Returns the workspace named by the window's `_NET_WM_DESKTOP`.
+    fn get_window_desktop(&self, window_id: &WindowId) -> Option<usize>;
+
+    /// Whether the urgency bit is set in the window's `WM_HINTS`.
+    fn is_urgent(&self, window_id: &WindowId) -> bool;
+
+    /// Returns the window that this one is a dialog for, from its
+    /// `WM_TRANSIENT_FOR`.
+    fn get_transient_for(&self, window_id: &WindowId) -> Option<WindowId>;
+
+    /// Sets the window's `_NET_WM_DESKTOP`.
+    fn set_window_desktop(&self, window_id: &WindowId, desktop: usize);
+
+    /// Deletes the window's `_NET_WM_DESKTOP`, as it is withdrawn.
+    fn remove_window_desktop(&self, window_id: &WindowId);
+
+    /// Replaces the window's `_NET_WM_STATE`.
+    fn set_window_states(&self, window_id: &WindowId, states: &[WindowState]);
+
+    /// Returns the area reserved by a dock, if any.
+    fn get_strut(&self, window_id: &WindowId) -> Option<Strut>;
+
+    /// Send the current set of windows and workspaces to any listeners to EHWM updates.
+    fn update_ewmh_desktops(&self, groups: &[Group], focused: usize, windows: Vec<&WindowId>);
+
+    /// Publishes the size of the screen, and the usable area for each
+    /// workspace.
+    fn update_ewmh_geometry(&self, width: u32, height: u32, workarea: &[Viewport]);
+
+    /// Closes a window.
+    fn close_window(&self, window_id: &WindowId);
+
+    /// Disconnects the client that owns a window, destroying all of its
+    /// windows.
+    fn kill_window(&self, window_id: &WindowId);
+
+    /// Sends the window a `_NET_WM_PING`, returning false if it doesn't
+    /// support it.
+    fn ping(&self, window_id: &WindowId) -> bool;
+
+    /// Gets the window's position and size.
+    fn get_window_geometry(&self, window_id: &WindowId) -> Option<Viewport>;
+
+    /// Returns the size constraints from the window's `WM_NORMAL_HINTS`.
+    fn get_size_hints(&self, window_id: &WindowId) -> SizeHints;
+
+    /// Sets the window's position and size.
+    fn configure_window(&self, window_id: &WindowId, x: u32, y: u32, width: u32, height: u32);
+
+    /// Grants a client's request to configure its window, unchanged.
+    fn grant_configure_request(&self, request: &ConfigureRequest);
+
+    /// Tells a client where its window is, as though it had just been
+    /// configured, without changing anything.
+    fn send_configure_notify(&self, window_id: &WindowId, viewport: &Viewport);
+
+    /// Stacks a window above its siblings.
+    fn raise_window(&self, window_id: &WindowId);
+
+    /// Map a window.
+    fn map_window(&self, window_id: &WindowId);
+
+    /// Unmap a window.
+    fn unmap_window(&self, window_id: &WindowId);
+
+    /// Registers for key events.
+    fn enable_window_key_events(&self, window_id: &WindowId, key_combos: &[&KeyCombo]);
+
+    /// Releases every key registered with `enable_window_key_events`.
+    fn disable_window_key_events(&self, window_id: &WindowId);
+
+    fn enable_window_tracking(&self, window_id: &WindowId);
+
+    fn disable_window_tracking(&self, window_id: &WindowId);
+
+    /// Gives a window the input focus and marks it as active, or gives the
+    /// focus to the root window if there is none.
+    fn focus(&self, window: Option<&WindowId>);
+
+    /// Marks the window as active, without giving it the input focus.
+    fn set_active_window(&self, window_id: &WindowId);
+
+    /// Returns how the window expects to be focused, from its `WM_HINTS` and
+    /// `WM_PROTOCOLS`.
+    fn get_input_model(&self, window_id: &WindowId) -> InputModel;
+
+    /// Sends the client a `WM_TAKE_FOCUS` message.
+    fn send_take_focus(&self, window_id: &WindowId);
+
+    /// Returns an iterator over the events received from the X server.
+    ///
+    /// The iterator also yields `Event::Readable` whenever one of the `watched`
+    /// file descriptors becomes readable.
+    fn get_event_loop(&self, watched: &[RawFd]) -> Box<dyn Iterator<Item = Event> + '_>;
 }
 
 pub struct Connection {
@@ -192,9 +449,6 @@ impl Connection {
             WindowState::DemandsAttention,
         );
 
-        let supported_atoms = [conn.ACTIVE_WINDOW(), conn.CURRENT_DESKTOP()];
-        ewmh::set_supported(&conn, screen_idx, &supported_atoms);
-
         Ok(Connection {
             conn,
             root: WindowId(root),
@@ -206,6 +460,67 @@ impl Connection {
         })
     }
 
+    /// Lists the EWMH hints that Lanta understands, for `_NET_SUPPORTED`.
+    fn supported_atoms(&self) -> Vec<xcb::Atom> {
+        let conn = &self.conn;
+        vec![
+            conn.SUPPORTED(),
+            conn.SUPPORTING_WM_CHECK(),
+            conn.WM_NAME(),
+            // Root window properties and the client messages that change them.
+            conn.NUMBER_OF_DESKTOPS(),
+            conn.DESKTOP_NAMES(),
+            conn.CURRENT_DESKTOP(),
+            conn.CLIENT_LIST(),
+            conn.DESKTOP_GEOMETRY(),
+            conn.DESKTOP_VIEWPORT(),
+            conn.WORKAREA(),
+            conn.ACTIVE_WINDOW(),
+            conn.CLOSE_WINDOW(),
+            conn.WM_PING(),
+            // Window properties.
+            conn.WM_DESKTOP(),
+            conn.WM_STRUT_PARTIAL(),
+            conn.WM_STATE(),
+            conn.WM_STATE_FULLSCREEN(),
+            conn.WM_STATE_MODAL(),
+            conn.WM_STATE_DEMANDS_ATTENTION(),
+            conn.WM_WINDOW_TYPE(),
+            conn.WM_WINDOW_TYPE_DOCK(),
+            conn.WM_WINDOW_TYPE_DIALOG(),
+            conn.WM_WINDOW_TYPE_SPLASH(),
+            conn.WM_WINDOW_TYPE_UTILITY(),
+            conn.WM_WINDOW_TYPE_TOOLBAR(),
+            conn.WM_WINDOW_TYPE_MENU(),
+            conn.WM_WINDOW_TYPE_NOTIFICATION(),
+            conn.WM_WINDOW_TYPE_TOOLTIP(),
+            conn.WM_WINDOW_TYPE_NORMAL(),
+        ]
+    }
+
+    /// Creates the hidden window that tells other clients which window
+    /// manager is running.
+    fn create_check_window(&self) {
+        let window = self.conn.generate_id();
+        xcb::create_window(
+            &self.conn,
+            xcb::COPY_FROM_PARENT as u8,
+            window,
+            self.root.to_x(),
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &[(xcb::CW_OVERRIDE_REDIRECT, 1)],
+        );
+        ewmh::set_supporting_wm_check(&self.conn, self.root.to_x(), window);
+        ewmh::set_supporting_wm_check(&self.conn, window, window);
+        ewmh::set_wm_name(&self.conn, window, WM_NAME);
+    }
+
     /// Returns the Atom identifier associated with the atom_name str.
     fn intern_atom(conn: &xcb::Connection, atom_name: &str) -> Result<xcb::Atom> {
         Ok(xcb::intern_atom(conn, false, atom_name).get_reply()?.atom())
@@ -219,7 +534,47 @@ impl Connection {
         randr::get_crtc_info(&self.conn, crtc, 0)
     }
 
-    pub fn list_crtc(&self) -> Result<Vec<(randr::Crtc, CrtcInfo)>> {
+    pub fn get_window_attributes(&self, w_id: &WindowId) -> Result<xcb::GetWindowAttributesReply> {
+        Ok(xcb::get_window_attributes(&self.conn, w_id.to_x()).get_reply()?)
+    }
+
+    /// Queries the WM_PROTOCOLS property of a window, returning a list of the
+    /// protocols that it supports.
+    fn get_wm_protocols(&self, window_id: &WindowId) -> Result<Vec<xcb::Atom>> {
+        let reply = icccm::get_wm_protocols(&self.conn, window_id.to_x(), self.atoms.WM_PROTOCOLS)
+            .get_reply()?;
+        Ok(reply.atoms().to_vec())
+    }
+
+    fn supports_protocol(&self, window_id: &WindowId, protocol: xcb::Atom) -> bool {
+        self.get_wm_protocols(window_id)
+            .map(|protocols| protocols.contains(&protocol))
+            .unwrap_or(false)
+    }
+
+    /// Sends one of the `WM_PROTOCOLS` client messages to a window.
+    fn send_protocol_message(&self, window_id: &WindowId, protocol: xcb::Atom) {
+        let data = xcb::ClientMessageData::from_data32([protocol, xcb::CURRENT_TIME, 0, 0, 0]);
+        let event =
+            xcb::ClientMessageEvent::new(32, window_id.to_x(), self.atoms.WM_PROTOCOLS, data);
+        xcb::send_event(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            xcb::EVENT_MASK_NO_EVENT,
+            &event,
+        );
+    }
+
+    pub fn get_strut_partial(&self, window_id: &WindowId) -> Option<StrutPartial> {
+        ewmh::get_wm_strut_partial(&self.conn, window_id.to_x())
+            .get_reply()
+            .ok()
+    }
+}
+
+impl XConnection for Connection {
+    fn list_crtc(&self) -> Result<Vec<(randr::Crtc, CrtcInfo)>> {
         let screen_res = randr::get_screen_resources(&self.conn, self.root.to_x()).get_reply()?;
         let crtc_cookies: Vec<(randr::Crtc, randr::GetCrtcInfoCookie)> = screen_res
             .crtcs()
@@ -241,7 +596,10 @@ impl Connection {
     /// SubstructureNotify and SubstructureRedirect events on the root window.
     /// If there is already a window manager on the display, then this will
     /// fail.
-    pub fn install_as_wm(&self, key_handlers: &KeyHandlers) -> Result<()> {
+    ///
+    /// Once installed, Lanta announces itself to other clients as an EWMH
+    /// window manager named "lanta".
+    fn install_as_wm(&self, key_combos: &[&KeyCombo]) -> Result<()> {
         let values = [(
             xcb::CW_EVENT_MASK,
             xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
@@ -249,31 +607,19 @@ impl Connection {
         xcb::change_window_attributes_checked(&self.conn, self.root.to_x(), &values)
             .request_check()?;
 
-        self.enable_window_key_events(&self.root, key_handlers);
+        self.enable_window_key_events(&self.root, key_combos);
 
-        Ok(())
-    }
+        ewmh::set_supported(&self.conn, self.screen_idx, &self.supported_atoms());
+        self.create_check_window();
 
-    pub fn get_window_attributes(&self, w_id: &WindowId) -> Result<xcb::GetWindowAttributesReply> {
-        Ok(xcb::get_window_attributes(&self.conn, w_id.to_x()).get_reply()?)
+        Ok(())
     }
 
-    /// Returns the ID of the root window.
-    pub fn root_window_id(&self) -> &WindowId {
+    fn root_window_id(&self) -> &WindowId {
         &self.root
     }
 
-    /// Send the current set of windows and workspaces to any listeners to EHWM updates.
-    pub fn update_ewmh_desktops(&self, groups: &[Group], focused: usize, windows: Vec<&WindowId>) {
-        let group_names = groups.iter().map(|g| g.name());
-        ewmh::set_desktop_names(&self.conn, self.screen_idx, group_names);
-        ewmh::set_number_of_desktops(&self.conn, self.screen_idx, groups.len() as u32);
-        let windows = windows.iter().map(|w| w.to_x()).collect::<Vec<_>>();
-        ewmh::set_client_list(&self.conn, self.screen_idx, &windows);
-        ewmh::set_current_desktop(&self.conn, self.screen_idx, focused as u32);
-    }
-
-    pub fn top_level_windows(&self) -> Result<Vec<WindowId>> {
+    fn top_level_windows(&self) -> Result<Vec<WindowId>> {
         let windows = xcb::query_tree(&self.conn, self.root.to_x())
             .get_reply()?
             .children()
@@ -283,15 +629,49 @@ impl Connection {
         Ok(windows)
     }
 
-    /// Queries the WM_PROTOCOLS property of a window, returning a list of the
-    /// protocols that it supports.
-    fn get_wm_protocols(&self, window_id: &WindowId) -> Result<Vec<xcb::Atom>> {
-        let reply = icccm::get_wm_protocols(&self.conn, window_id.to_x(), self.atoms.WM_PROTOCOLS)
-            .get_reply()?;
-        Ok(reply.atoms().to_vec())
+    fn is_override_redirect(&self, window_id: &WindowId) -> Result<bool> {
+        Ok(self.get_window_attributes(window_id)?.override_redirect())
+    }
+
+    fn is_viewable(&self, window_id: &WindowId) -> bool {
+        self.get_window_attributes(window_id)
+            .map(|attrs| attrs.map_state() == xcb::MAP_STATE_VIEWABLE as u8)
+            .unwrap_or(false)
+    }
+
+    fn get_wm_state(&self, window_id: &WindowId) -> Option<WmState> {
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            self.atoms.WM_STATE,
+            self.atoms.WM_STATE,
+            0,
+            2,
+        )
+        .get_reply()
+        .ok()?;
+        reply
+            .value::<u32>()
+            .first()
+            .and_then(|&state| WmState::from_x(state))
+    }
+
+    fn set_wm_state(&self, window_id: &WindowId, state: WmState) {
+        // The second field is the icon window, which Lanta doesn't use.
+        let data = [state.to_x(), xcb::NONE];
+        xcb::change_property(
+            &self.conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window_id.to_x(),
+            self.atoms.WM_STATE,
+            self.atoms.WM_STATE,
+            32,
+            &data,
+        );
     }
 
-    pub fn get_window_types(&self, window_id: &WindowId) -> Vec<WindowType> {
+    fn get_window_types(&self, window_id: &WindowId) -> Vec<WindowType> {
         // Filter out any types we don't understand, as that's what the EWMH
         // spec suggests we should do. Don't error if _NET_WM_WINDOW_TYPE
         // is not set - lots of applications don't bother.
@@ -307,7 +687,44 @@ impl Connection {
             .unwrap_or_else(|_| Vec::new())
     }
 
-    pub fn get_window_states(&self, window_id: &WindowId) -> Vec<WindowState> {
+    fn get_wm_class(&self, window_id: &WindowId) -> Option<(String, String)> {
+        icccm::get_wm_class(&self.conn, window_id.to_x())
+            .get_reply()
+            .ok()
+            .map(|reply| (reply.instance().to_owned(), reply.class().to_owned()))
+    }
+
+    fn get_window_title(&self, window_id: &WindowId) -> Option<String> {
+        ewmh::get_wm_name(&self.conn, window_id.to_x())
+            .get_reply()
+            .map(|reply| reply.string().to_owned())
+            .or_else(|_| {
+                icccm::get_wm_name(&self.conn, window_id.to_x())
+                    .get_reply()
+                    .map(|reply| reply.name().to_owned())
+            })
+            .ok()
+    }
+
+    fn get_window_role(&self, window_id: &WindowId) -> Option<String> {
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            self.atoms.WM_WINDOW_ROLE,
+            xcb::ATOM_STRING,
+            0,
+            1024,
+        )
+        .get_reply()
+        .ok()?;
+        if reply.value_len() == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(reply.value::<u8>()).into_owned())
+    }
+
+    fn get_window_states(&self, window_id: &WindowId) -> Vec<WindowState> {
         // EWMH states to ignore any we don't understand.
         // Don't error if no window states set.
         ewmh::get_wm_state(&self.conn, window_id.to_x())
@@ -322,48 +739,135 @@ impl Connection {
             .unwrap_or_else(|_| Vec::new())
     }
 
-    pub fn get_strut_partial(&self, window_id: &WindowId) -> Option<StrutPartial> {
-        ewmh::get_wm_strut_partial(&self.conn, window_id.to_x())
+    fn get_window_desktop(&self, window_id: &WindowId) -> Option<usize> {
+        ewmh::get_wm_desktop(&self.conn, window_id.to_x())
             .get_reply()
             .ok()
+            .map(|desktop| desktop as usize)
+    }
+
+    fn is_urgent(&self, window_id: &WindowId) -> bool {
+        // xcb_util::icccm::WmHints::is_urgent() tests the wrong bit, so the
+        // flags are read by hand.
+        const URGENCY_HINT: u32 = 1 << 8;
+        xcb::get_property(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            xcb::ATOM_WM_HINTS,
+            xcb::ATOM_WM_HINTS,
+            0,
+            1,
+        )
+        .get_reply()
+        .ok()
+        .and_then(|reply| reply.value::<u32>().first().cloned())
+        .is_some_and(|flags| flags & URGENCY_HINT != 0)
+    }
+
+    fn get_transient_for(&self, window_id: &WindowId) -> Option<WindowId> {
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            xcb::ATOM_WM_TRANSIENT_FOR,
+            xcb::ATOM_WINDOW,
+            0,
+            1,
+        )
+        .get_reply()
+        .ok()?;
+        reply
+            .value::<xcb::Window>()
+            .first()
+            .filter(|&&w| w != xcb::NONE)
+            .map(|&w| WindowId(w))
+    }
+
+    fn set_window_desktop(&self, window_id: &WindowId, desktop: usize) {
+        ewmh::set_wm_desktop(&self.conn, window_id.to_x(), desktop as u32);
+    }
+
+    fn remove_window_desktop(&self, window_id: &WindowId) {
+        xcb::delete_property(&self.conn, window_id.to_x(), self.conn.WM_DESKTOP());
+    }
+
+    fn set_window_states(&self, window_id: &WindowId, states: &[WindowState]) {
+        let atoms: Vec<xcb::Atom> = states
+            .iter()
+            .filter_map(|state| {
+                self.window_state_lookup
+                    .iter()
+                    .find(|(_, s)| *s == state)
+                    .map(|(&atom, _)| atom)
+            })
+            .collect();
+        ewmh::set_wm_state(&self.conn, window_id.to_x(), &atoms);
+    }
+
+    fn get_strut(&self, window_id: &WindowId) -> Option<Strut> {
+        self.get_strut_partial(window_id)
+            .as_ref()
+            .map(Strut::from_strut_partial)
+    }
+
+    fn update_ewmh_desktops(&self, groups: &[Group], focused: usize, windows: Vec<&WindowId>) {
+        let group_names = groups.iter().map(|g| g.name());
+        ewmh::set_desktop_names(&self.conn, self.screen_idx, group_names);
+        ewmh::set_number_of_desktops(&self.conn, self.screen_idx, groups.len() as u32);
+        let windows = windows.iter().map(|w| w.to_x()).collect::<Vec<_>>();
+        ewmh::set_client_list(&self.conn, self.screen_idx, &windows);
+        ewmh::set_current_desktop(&self.conn, self.screen_idx, focused as u32);
+    }
+
+    fn update_ewmh_geometry(&self, width: u32, height: u32, workarea: &[Viewport]) {
+        ewmh::set_desktop_geometry(&self.conn, self.screen_idx, width, height);
+        // Workspaces are never larger than the screen, so never scroll.
+        let origins: Vec<_> = workarea
+            .iter()
+            .map(|_| ewmh::Coordinates { x: 0, y: 0 })
+            .collect();
+        ewmh::set_desktop_viewport(&self.conn, self.screen_idx, &origins);
+        let workarea: Vec<_> = workarea
+            .iter()
+            .map(|vp| ewmh::Geometry {
+                x: vp.x,
+                y: vp.y,
+                width: vp.width,
+                height: vp.height,
+            })
+            .collect();
+        ewmh::set_work_area(&self.conn, self.screen_idx, &workarea);
     }
 
     /// Closes a window.
     ///
     /// The window will be closed gracefully using the ICCCM WM_DELETE_WINDOW
     /// protocol if it is supported.
-    pub fn close_window(&self, window_id: &WindowId) {
-        let has_wm_delete_window = self
-            .get_wm_protocols(window_id)
-            .map(|protocols| protocols.contains(&self.atoms.WM_DELETE_WINDOW))
-            .unwrap_or(false);
-
-        if has_wm_delete_window {
+    fn close_window(&self, window_id: &WindowId) {
+        if self.supports_protocol(window_id, self.atoms.WM_DELETE_WINDOW) {
             info!("Closing window {} using WM_DELETE", window_id);
-            let data = xcb::ClientMessageData::from_data32([
-                self.atoms.WM_DELETE_WINDOW,
-                xcb::CURRENT_TIME,
-                0,
-                0,
-                0,
-            ]);
-            let event =
-                xcb::ClientMessageEvent::new(32, window_id.to_x(), self.atoms.WM_PROTOCOLS, data);
-            xcb::send_event(
-                &self.conn,
-                false,
-                window_id.to_x(),
-                xcb::EVENT_MASK_NO_EVENT,
-                &event,
-            );
+            self.send_protocol_message(window_id, self.atoms.WM_DELETE_WINDOW);
         } else {
             info!("Closing window {} using xcb::destroy_window()", window_id);
             xcb::destroy_window(&self.conn, window_id.to_x());
         }
     }
 
-    /// Sets the window's position and size.
-    pub fn configure_window(&self, window_id: &WindowId, x: u32, y: u32, width: u32, height: u32) {
+    fn kill_window(&self, window_id: &WindowId) {
+        info!("Killing the client of window {}", window_id);
+        xcb::kill_client(&self.conn, window_id.to_x());
+    }
+
+    fn ping(&self, window_id: &WindowId) -> bool {
+        if !self.supports_protocol(window_id, self.conn.WM_PING()) {
+            return false;
+        }
+        ewmh::send_wm_ping(&self.conn, window_id.to_x(), xcb::CURRENT_TIME);
+        true
+    }
+
+    fn configure_window(&self, window_id: &WindowId, x: u32, y: u32, width: u32, height: u32) {
         let values = [
             (xcb::CONFIG_WINDOW_X as u16, x),
             (xcb::CONFIG_WINDOW_Y as u16, y),
@@ -373,31 +877,81 @@ impl Connection {
         xcb::configure_window(&self.conn, window_id.to_x(), &values);
     }
 
-    /// Get's the window's width and height.
-    pub fn get_window_geometry(&self, window_id: &WindowId) -> (u32, u32) {
+    fn get_window_geometry(&self, window_id: &WindowId) -> Option<Viewport> {
         let reply = xcb::get_geometry(&self.conn, window_id.to_x())
             .get_reply()
-            .unwrap();
+            .ok()?;
         // Cast as everywhere else uses u32.
-        (u32::from(reply.width()), u32::from(reply.height()))
+        Some(Viewport {
+            x: cmp::max(reply.x(), 0) as u32,
+            y: cmp::max(reply.y(), 0) as u32,
+            width: u32::from(reply.width()),
+            height: u32::from(reply.height()),
+        })
     }
 
-    /// Map a window.
-    pub fn map_window(&self, window_id: &WindowId) {
+    fn grant_configure_request(&self, request: &ConfigureRequest) {
+        xcb::configure_window(&self.conn, request.window.to_x(), &request.values());
+    }
+
+    fn send_configure_notify(&self, window_id: &WindowId, viewport: &Viewport) {
+        let event = xcb::ConfigureNotifyEvent::new(
+            window_id.to_x(),
+            window_id.to_x(),
+            xcb::NONE,
+            viewport.x as i16,
+            viewport.y as i16,
+            viewport.width as u16,
+            viewport.height as u16,
+            0,
+            false,
+        );
+        xcb::send_event(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            xcb::EVENT_MASK_STRUCTURE_NOTIFY,
+            &event,
+        );
+    }
+
+    fn get_size_hints(&self, window_id: &WindowId) -> SizeHints {
+        // The accessors in xcb_util::icccm only work for the first flag, so the
+        // property is read by hand.
+        let cookie = xcb::get_property(
+            &self.conn,
+            false,
+            window_id.to_x(),
+            xcb::ATOM_WM_NORMAL_HINTS,
+            xcb::ATOM_WM_SIZE_HINTS,
+            0,
+            18,
+        );
+        match cookie.get_reply() {
+            Ok(reply) if reply.format() == 32 => SizeHints::from_wm_size_hints(reply.value()),
+            _ => SizeHints::default(),
+        }
+    }
+
+    fn raise_window(&self, window_id: &WindowId) {
+        let values = [(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)];
+        xcb::configure_window(&self.conn, window_id.to_x(), &values);
+    }
+
+    fn map_window(&self, window_id: &WindowId) {
         xcb::map_window(&self.conn, window_id.to_x());
     }
 
-    /// Unmap a window.
-    pub fn unmap_window(&self, window_id: &WindowId) {
+    fn unmap_window(&self, window_id: &WindowId) {
         xcb::unmap_window(&self.conn, window_id.to_x());
     }
 
     /// Registers for key events.
     ///
     /// If it fails to register any of the keys, it will log an error and continue.
-    pub fn enable_window_key_events(&self, window_id: &WindowId, key_handlers: &KeyHandlers) {
+    fn enable_window_key_events(&self, window_id: &WindowId, key_combos: &[&KeyCombo]) {
         let key_symbols = KeySymbols::new(&self.conn);
-        for key in key_handlers.key_combos() {
+        for key in key_combos {
             match key_symbols.get_keycode(key.keysym).next() {
                 Some(keycode) => {
                     xcb::grab_key(
@@ -420,20 +974,31 @@ impl Connection {
         }
     }
 
-    pub fn enable_window_tracking(&self, window_id: &WindowId) {
+    fn disable_window_key_events(&self, window_id: &WindowId) {
+        xcb::ungrab_key(
+            &self.conn,
+            xcb::GRAB_ANY as u8,
+            window_id.to_x(),
+            xcb::MOD_MASK_ANY as u16,
+        );
+    }
+
+    fn enable_window_tracking(&self, window_id: &WindowId) {
         let values = [(
             xcb::CW_EVENT_MASK,
-            xcb::EVENT_MASK_ENTER_WINDOW | xcb::EVENT_MASK_STRUCTURE_NOTIFY,
+            xcb::EVENT_MASK_ENTER_WINDOW
+                | xcb::EVENT_MASK_STRUCTURE_NOTIFY
+                | xcb::EVENT_MASK_PROPERTY_CHANGE,
         )];
         xcb::change_window_attributes(&self.conn, window_id.to_x(), &values);
     }
 
-    pub fn disable_window_tracking(&self, window_id: &WindowId) {
+    fn disable_window_tracking(&self, window_id: &WindowId) {
         let values = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
         xcb::change_window_attributes(&self.conn, window_id.to_x(), &values);
     }
 
-    pub fn focus(&self, window: Option<&WindowId>) {
+    fn focus(&self, window: Option<&WindowId>) {
         match window {
             Some(window_id) => {
                 xcb::set_input_focus(
@@ -445,12 +1010,37 @@ impl Connection {
                 ewmh::set_active_window(&self.conn, self.screen_idx, window_id.to_x());
             }
             None => {
+                xcb::set_input_focus(
+                    &self.conn,
+                    xcb::INPUT_FOCUS_POINTER_ROOT as u8,
+                    self.root.to_x(),
+                    xcb::CURRENT_TIME,
+                );
                 ewmh::set_active_window(&self.conn, self.screen_idx, xcb::NONE);
             }
         }
     }
 
-    pub fn get_event_loop(&self) -> EventLoop<'_> {
+    fn set_active_window(&self, window_id: &WindowId) {
+        ewmh::set_active_window(&self.conn, self.screen_idx, window_id.to_x());
+    }
+
+    fn get_input_model(&self, window_id: &WindowId) -> InputModel {
+        // Clients that don't say otherwise are assumed to want input.
+        let input = icccm::get_wm_hints(&self.conn, window_id.to_x())
+            .get_reply()
+            .ok()
+            .and_then(|hints| hints.input())
+            .unwrap_or(true);
+        let take_focus = self.supports_protocol(window_id, self.atoms.WM_TAKE_FOCUS);
+        InputModel::new(input, take_focus)
+    }
+
+    fn send_take_focus(&self, window_id: &WindowId) {
+        self.send_protocol_message(window_id, self.atoms.WM_TAKE_FOCUS);
+    }
+
+    fn get_event_loop(&self, watched: &[RawFd]) -> Box<dyn Iterator<Item = Event> + '_> {
         let _ = randr::select_input(
             &self.conn,
             self.root.to_x(),
@@ -458,7 +1048,10 @@ impl Connection {
         )
         .request_check();
         self.flush();
-        EventLoop { connection: self }
+        Box::new(EventLoop {
+            connection: self,
+            watched: watched.to_vec(),
+        })
     }
 }
 
@@ -494,11 +1087,32 @@ impl From<randr::CrtcChange> for CrtcChange {
 /// Events received from the `EventLoop`.
 pub enum Event {
     MapRequest(WindowId),
+    ConfigureRequest(ConfigureRequest),
     UnmapNotify(WindowId),
     DestroyNotify(WindowId),
     KeyPress(KeyCombo),
     EnterNotify(WindowId),
     CrtcChange(CrtcChange),
+    /// A pager asked for a group to be shown.
+    CurrentDesktopRequest(usize),
+    /// A pager asked for there to be this many groups.
+    NumberOfDesktopsRequest(usize),
+    /// A pager asked for a window to be focused.
+    ActiveWindowRequest(WindowId),
+    /// A pager asked for a window to be closed.
+    CloseWindowRequest(WindowId),
+    /// A pager or client asked for a window to be moved to a group.
+    WindowDesktopRequest(WindowId, usize),
+    /// A client asked for its `_NET_WM_STATE` to change.
+    StateRequest(WindowId, StateAction, Vec<WindowState>),
+    /// A client answered a `_NET_WM_PING`.
+    PingReply(WindowId),
+    /// A client changed its `WM_HINTS`.
+    HintsChanged(WindowId),
+    /// A client changed its `WM_NORMAL_HINTS`.
+    SizeHintsChanged(WindowId),
+    /// A watched file descriptor is ready to be read.
+    Readable(RawFd),
 }
 
 /// An iterator that yields events from the X event loop.
@@ -506,6 +1120,7 @@ pub enum Event {
 /// Use `Connection::get_event_loop()` to get one.
 pub struct EventLoop<'a> {
     connection: &'a Connection,
+    watched: Vec<RawFd>,
 }
 
 impl<'a> Iterator for EventLoop<'a> {
@@ -517,11 +1132,18 @@ impl<'a> Iterator for EventLoop<'a> {
             // have) just yielded.
             self.connection.flush();
 
-            let event = self
-                .connection
-                .conn
-                .wait_for_event()
-                .expect("wait_for_event() returned None: IO error?");
+            let event = match self.connection.conn.poll_for_event() {
+                Some(event) => event,
+                None => {
+                    if self.connection.conn.has_error().is_err() {
+                        panic!("poll_for_event() returned None: IO error?");
+                    }
+                    if let Some(fd) = self.wait_readable() {
+                        return Some(Event::Readable(fd));
+                    }
+                    continue;
+                }
+            };
 
             unsafe {
                 let randr_notify = self.connection.randr_base + randr::NOTIFY;
@@ -532,6 +1154,8 @@ impl<'a> Iterator for EventLoop<'a> {
                     xcb::DESTROY_NOTIFY => self.on_destroy_notify(xcb::cast_event(&event)),
                     xcb::KEY_PRESS => self.on_key_press(xcb::cast_event(&event)),
                     xcb::ENTER_NOTIFY => self.on_enter_notify(xcb::cast_event(&event)),
+                    xcb::CLIENT_MESSAGE => self.on_client_message(xcb::cast_event(&event)),
+                    xcb::PROPERTY_NOTIFY => self.on_property_notify(xcb::cast_event(&event)),
                     n if n == randr_notify => self.on_randr_notify(xcb::cast_event(&event)),
                     _ => None,
                 };
@@ -545,33 +1169,44 @@ impl<'a> Iterator for EventLoop<'a> {
 }
 
 impl<'a> EventLoop<'a> {
-    fn on_configure_request(&self, event: &xcb::ConfigureRequestEvent) -> Option<Event> {
-        // This request is not interesting for us: grant it unchanged.
-        // Build a request with all attributes set, then filter out to only include
-        // those from the original request.
-        let values = vec![
-            (xcb::CONFIG_WINDOW_X as u16, event.x() as u32),
-            (xcb::CONFIG_WINDOW_Y as u16, event.y() as u32),
-            (xcb::CONFIG_WINDOW_WIDTH as u16, u32::from(event.width())),
-            (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(event.height())),
-            (
-                xcb::CONFIG_WINDOW_BORDER_WIDTH as u16,
-                u32::from(event.border_width()),
-            ),
-            (xcb::CONFIG_WINDOW_SIBLING as u16, event.sibling() as u32),
-            (
-                xcb::CONFIG_WINDOW_STACK_MODE as u16,
-                u32::from(event.stack_mode()),
-            ),
-        ];
-        let filtered_values: Vec<_> = values
-            .into_iter()
-            .filter(|&(mask, _)| mask & event.value_mask() != 0)
+    /// Blocks until either the X connection or a watched file descriptor is
+    /// readable, returning the latter if it was.
+    fn wait_readable(&self) -> Option<RawFd> {
+        let mut fds: Vec<_> = std::iter::once(self.connection.conn.as_raw_fd())
+            .chain(self.watched.iter().cloned())
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
             .collect();
-        xcb::configure_window(&self.connection.conn, event.window(), &filtered_values);
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if ready < 0 {
+            // Most likely interrupted by a signal; the caller will try again.
+            return None;
+        }
+        fds.iter()
+            .skip(1)
+            .find(|pfd| pfd.revents != 0)
+            .map(|pfd| pfd.fd)
+    }
 
-        // There's no value in propogating this event.
-        None
+    fn on_configure_request(&self, event: &xcb::ConfigureRequestEvent) -> Option<Event> {
+        let mask = event.value_mask();
+        let has = |flag: u32| mask & flag as u16 != 0;
+        let request = ConfigureRequest {
+            window: WindowId(event.window()),
+            x: Some(i32::from(event.x())).filter(|_| has(xcb::CONFIG_WINDOW_X)),
+            y: Some(i32::from(event.y())).filter(|_| has(xcb::CONFIG_WINDOW_Y)),
+            width: Some(u32::from(event.width())).filter(|_| has(xcb::CONFIG_WINDOW_WIDTH)),
+            height: Some(u32::from(event.height())).filter(|_| has(xcb::CONFIG_WINDOW_HEIGHT)),
+            border_width: Some(u32::from(event.border_width()))
+                .filter(|_| has(xcb::CONFIG_WINDOW_BORDER_WIDTH)),
+            sibling: Some(WindowId(event.sibling())).filter(|_| has(xcb::CONFIG_WINDOW_SIBLING)),
+            stack_mode: Some(u32::from(event.stack_mode()))
+                .filter(|_| has(xcb::CONFIG_WINDOW_STACK_MODE)),
+        };
+        Some(Event::ConfigureRequest(request))
     }
 
     fn on_map_request(&self, event: &xcb::MapRequestEvent) -> Option<Event> {
@@ -594,6 +1229,16 @@ impl<'a> EventLoop<'a> {
         Some(Event::DestroyNotify(WindowId(event.window())))
     }
 
+    fn on_property_notify(&self, event: &xcb::PropertyNotifyEvent) -> Option<Event> {
+        if event.atom() == xcb::ATOM_WM_HINTS {
+            Some(Event::HintsChanged(WindowId(event.window())))
+        } else if event.atom() == xcb::ATOM_WM_NORMAL_HINTS {
+            Some(Event::SizeHintsChanged(WindowId(event.window())))
+        } else {
+            None
+        }
+    }
+
     fn on_key_press(&self, event: &xcb::KeyPressEvent) -> Option<Event> {
         let key_symbols = KeySymbols::new(&self.connection.conn);
         let keysym = key_symbols.press_lookup_keysym(event, 0);
@@ -606,6 +1251,42 @@ impl<'a> EventLoop<'a> {
         Some(Event::EnterNotify(WindowId(event.event())))
     }
 
+    fn on_client_message(&self, event: &xcb::ClientMessageEvent) -> Option<Event> {
+        let conn = &self.connection.conn;
+        let window = WindowId(event.window());
+        let data = event.data().data32();
+        if event.type_() == conn.WM_STATE() {
+            let action = match data[0] {
+                ewmh::STATE_REMOVE => StateAction::Remove,
+                ewmh::STATE_ADD => StateAction::Add,
+                ewmh::STATE_TOGGLE => StateAction::Toggle,
+                _ => return None,
+            };
+            // A client may change up to two states at once.
+            let states = data[1..3]
+                .iter()
+                .filter_map(|a| self.connection.window_state_lookup.get(a).cloned())
+                .collect();
+            Some(Event::StateRequest(window, action, states))
+        } else if event.type_() == conn.CURRENT_DESKTOP() {
+            Some(Event::CurrentDesktopRequest(data[0] as usize))
+        } else if event.type_() == conn.NUMBER_OF_DESKTOPS() {
+            Some(Event::NumberOfDesktopsRequest(data[0] as usize))
+        } else if event.type_() == conn.ACTIVE_WINDOW() {
+            Some(Event::ActiveWindowRequest(window))
+        } else if event.type_() == conn.CLOSE_WINDOW() {
+            Some(Event::CloseWindowRequest(window))
+        } else if event.type_() == conn.WM_DESKTOP() {
+            Some(Event::WindowDesktopRequest(window, data[0] as usize))
+        } else if event.type_() == self.connection.atoms.WM_PROTOCOLS && data[0] == conn.WM_PING() {
+            // The reply is sent to the root window, and names the client's
+            // window in the third field.
+            Some(Event::PingReply(WindowId(data[2])))
+        } else {
+            None
+        }
+    }
+
     fn on_randr_notify(&self, event: &randr::NotifyEvent) -> Option<Event> {
         debug!("{}", event.sub_code());
         //TODO: match on sub_code
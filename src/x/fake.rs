@@ -0,0 +1,344 @@
+//! An in-memory stand-in for the X server, so that `Lanta` can be driven
+//! without a display.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::os::unix::io::RawFd;
+
+use super::{
+    ConfigureRequest, Crtc, CrtcInfo, Event, InputModel, WindowId, WindowState, WindowType,
+    WmState, XConnection,
+};
+use crate::groups::Group;
+use crate::keys::KeyCombo;
+use crate::viewport::{SizeHints, Strut, Viewport};
+use crate::Result;
+
+const ROOT: WindowId = WindowId(0);
+
+/// A request that Lanta made of the fake X server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Map(WindowId),
+    Unmap(WindowId),
+    Configure(WindowId, Viewport),
+    GrantConfigure(ConfigureRequest),
+    ConfigureNotify(WindowId, Viewport),
+    Raise(WindowId),
+    SetStates(WindowId, Vec<WindowState>),
+    SetDesktop(WindowId, usize),
+    RemoveDesktop(WindowId),
+    SetWmState(WindowId, WmState),
+    Focus(Option<WindowId>),
+    SetActive(WindowId),
+    TakeFocus(WindowId),
+    Close(WindowId),
+    Kill(WindowId),
+    Ping(WindowId),
+    GrabKeys(WindowId, Vec<KeyCombo>),
+    UngrabKeys(WindowId),
+    UpdateDesktops {
+        names: Vec<String>,
+        current: usize,
+        clients: Vec<WindowId>,
+    },
+    UpdateGeometry {
+        width: u32,
+        height: u32,
+        workarea: Vec<Viewport>,
+    },
+}
+
+/// The properties of a window known to the fake X server.
+#[derive(Clone, Debug, Default)]
+pub struct FakeWindow {
+    pub types: Vec<WindowType>,
+    pub states: Vec<WindowState>,
+    pub override_redirect: bool,
+    pub strut: Option<Strut>,
+    pub geometry: Option<Viewport>,
+    pub class: Option<(String, String)>,
+    pub title: Option<String>,
+    pub role: Option<String>,
+    pub desktop: Option<usize>,
+    pub wm_state: Option<WmState>,
+    pub size_hints: SizeHints,
+    pub input_model: InputModel,
+    /// Whether the window supports `_NET_WM_PING`.
+    pub ping: bool,
+    pub transient_for: Option<WindowId>,
+    /// Whether the urgency bit is set in the window's `WM_HINTS`.
+    pub urgent: bool,
+}
+
+#[derive(Default)]
+pub struct FakeConnection {
+    crtc: Vec<(Crtc, CrtcInfo)>,
+    windows: RefCell<HashMap<WindowId, FakeWindow>>,
+    top_level: RefCell<Vec<WindowId>>,
+    mapped: RefCell<HashSet<WindowId>>,
+    events: RefCell<VecDeque<Event>>,
+    requests: RefCell<Vec<Request>>,
+}
+
+impl FakeConnection {
+    pub fn new(crtc: Vec<(Crtc, CrtcInfo)>) -> FakeConnection {
+        FakeConnection {
+            crtc,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a window that exists before Lanta starts, as if it were left
+    /// behind by a previous window manager.
+    pub fn add_top_level_window(&self, id: u32, window: FakeWindow) -> WindowId {
+        let window = FakeWindow {
+            wm_state: window.wm_state.or(Some(WmState::Normal)),
+            ..window
+        };
+        let id = self.add_window(id, window);
+        self.top_level.borrow_mut().push(id);
+        id
+    }
+
+    /// Creates a window that Lanta has not yet been told about.
+    pub fn add_window(&self, id: u32, window: FakeWindow) -> WindowId {
+        let id = WindowId(id);
+        self.windows.borrow_mut().insert(id, window);
+        id
+    }
+
+    /// Changes the properties of a window, as its client would.
+    pub fn update_window(&self, window_id: &WindowId, fun: impl FnOnce(&mut FakeWindow)) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(window_id) {
+            fun(window);
+        }
+    }
+
+    /// Queues an event to be yielded by the event loop.
+    pub fn inject(&self, event: Event) {
+        self.events.borrow_mut().push_back(event);
+    }
+
+    /// Returns and forgets every request made so far.
+    pub fn take_requests(&self) -> Vec<Request> {
+        self.requests.replace(Vec::new())
+    }
+
+    pub fn is_mapped(&self, window_id: &WindowId) -> bool {
+        self.mapped.borrow().contains(window_id)
+    }
+
+    fn record(&self, request: Request) {
+        self.requests.borrow_mut().push(request);
+    }
+
+    fn window<T>(&self, window_id: &WindowId, fun: impl FnOnce(&FakeWindow) -> T) -> Option<T> {
+        self.windows.borrow().get(window_id).map(fun)
+    }
+}
+
+impl XConnection for FakeConnection {
+    fn list_crtc(&self) -> Result<Vec<(Crtc, CrtcInfo)>> {
+        Ok(self.crtc.clone())
+    }
+
+    fn install_as_wm(&self, key_combos: &[&KeyCombo]) -> Result<()> {
+        self.enable_window_key_events(&ROOT, key_combos);
+        Ok(())
+    }
+
+    fn root_window_id(&self) -> &WindowId {
+        &ROOT
+    }
+
+    fn top_level_windows(&self) -> Result<Vec<WindowId>> {
+        Ok(self.top_level.borrow().clone())
+    }
+
+    fn is_override_redirect(&self, window_id: &WindowId) -> Result<bool> {
+        self.window(window_id, |w| w.override_redirect)
+            .ok_or_else(|| format!("No such window {}", window_id).into())
+    }
+
+    fn is_viewable(&self, window_id: &WindowId) -> bool {
+        self.is_mapped(window_id)
+    }
+
+    fn get_wm_state(&self, window_id: &WindowId) -> Option<WmState> {
+        self.window(window_id, |w| w.wm_state).and_then(|s| s)
+    }
+
+    fn set_wm_state(&self, window_id: &WindowId, state: WmState) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(window_id) {
+            window.wm_state = Some(state);
+        }
+        self.record(Request::SetWmState(*window_id, state));
+    }
+
+    fn get_window_types(&self, window_id: &WindowId) -> Vec<WindowType> {
+        self.window(window_id, |w| w.types.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_wm_class(&self, window_id: &WindowId) -> Option<(String, String)> {
+        self.window(window_id, |w| w.class.clone()).and_then(|c| c)
+    }
+
+    fn get_window_title(&self, window_id: &WindowId) -> Option<String> {
+        self.window(window_id, |w| w.title.clone()).and_then(|t| t)
+    }
+
+    fn get_window_role(&self, window_id: &WindowId) -> Option<String> {
+        self.window(window_id, |w| w.role.clone()).and_then(|r| r)
+    }
+
+    fn get_window_states(&self, window_id: &WindowId) -> Vec<WindowState> {
+        self.window(window_id, |w| w.states.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_window_desktop(&self, window_id: &WindowId) -> Option<usize> {
+        self.window(window_id, |w| w.desktop).and_then(|d| d)
+    }
+
+    fn is_urgent(&self, window_id: &WindowId) -> bool {
+        self.window(window_id, |w| w.urgent).unwrap_or(false)
+    }
+
+    fn get_transient_for(&self, window_id: &WindowId) -> Option<WindowId> {
+        self.window(window_id, |w| w.transient_for).and_then(|t| t)
+    }
+
+    fn set_window_desktop(&self, window_id: &WindowId, desktop: usize) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(window_id) {
+            window.desktop = Some(desktop);
+        }
+        self.record(Request::SetDesktop(*window_id, desktop));
+    }
+
+    fn remove_window_desktop(&self, window_id: &WindowId) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(window_id) {
+            window.desktop = None;
+        }
+        self.record(Request::RemoveDesktop(*window_id));
+    }
+
+    fn set_window_states(&self, window_id: &WindowId, states: &[WindowState]) {
+        if let Some(window) = self.windows.borrow_mut().get_mut(window_id) {
+            window.states = states.to_vec();
+        }
+        self.record(Request::SetStates(*window_id, states.to_vec()));
+    }
+
+    fn get_strut(&self, window_id: &WindowId) -> Option<Strut> {
+        self.window(window_id, |w| w.strut.clone()).and_then(|s| s)
+    }
+
+    fn update_ewmh_desktops(&self, groups: &[Group], focused: usize, windows: Vec<&WindowId>) {
+        self.record(Request::UpdateDesktops {
+            names: groups.iter().map(|g| g.name().to_owned()).collect(),
+            current: focused,
+            clients: windows.into_iter().cloned().collect(),
+        });
+    }
+
+    fn update_ewmh_geometry(&self, width: u32, height: u32, workarea: &[Viewport]) {
+        self.record(Request::UpdateGeometry {
+            width,
+            height,
+            workarea: workarea.to_vec(),
+        });
+    }
+
+    fn close_window(&self, window_id: &WindowId) {
+        self.record(Request::Close(*window_id));
+    }
+
+    fn kill_window(&self, window_id: &WindowId) {
+        self.record(Request::Kill(*window_id));
+    }
+
+    fn ping(&self, window_id: &WindowId) -> bool {
+        if !self.window(window_id, |w| w.ping).unwrap_or(false) {
+            return false;
+        }
+        self.record(Request::Ping(*window_id));
+        true
+    }
+
+    fn configure_window(&self, window_id: &WindowId, x: u32, y: u32, width: u32, height: u32) {
+        let vp = Viewport {
+            x,
+            y,
+            width,
+            height,
+        };
+        self.record(Request::Configure(*window_id, vp));
+    }
+
+    fn grant_configure_request(&self, request: &ConfigureRequest) {
+        self.record(Request::GrantConfigure(request.clone()));
+    }
+
+    fn send_configure_notify(&self, window_id: &WindowId, viewport: &Viewport) {
+        self.record(Request::ConfigureNotify(*window_id, *viewport));
+    }
+
+    fn get_window_geometry(&self, window_id: &WindowId) -> Option<Viewport> {
+        self.window(window_id, |w| w.geometry).and_then(|g| g)
+    }
+
+    fn get_size_hints(&self, window_id: &WindowId) -> SizeHints {
+        self.window(window_id, |w| w.size_hints).unwrap_or_default()
+    }
+
+    fn raise_window(&self, window_id: &WindowId) {
+        self.record(Request::Raise(*window_id));
+    }
+
+    fn map_window(&self, window_id: &WindowId) {
+        self.mapped.borrow_mut().insert(*window_id);
+        self.record(Request::Map(*window_id));
+    }
+
+    fn unmap_window(&self, window_id: &WindowId) {
+        self.mapped.borrow_mut().remove(window_id);
+        self.record(Request::Unmap(*window_id));
+    }
+
+    fn enable_window_key_events(&self, window_id: &WindowId, key_combos: &[&KeyCombo]) {
+        let key_combos = key_combos.iter().map(|&k| k.clone()).collect();
+        self.record(Request::GrabKeys(*window_id, key_combos));
+    }
+
+    fn disable_window_key_events(&self, window_id: &WindowId) {
+        self.record(Request::UngrabKeys(*window_id));
+    }
+
+    fn enable_window_tracking(&self, _window_id: &WindowId) {}
+
+    fn disable_window_tracking(&self, _window_id: &WindowId) {}
+
+    fn focus(&self, window: Option<&WindowId>) {
+        self.record(Request::Focus(window.cloned()));
+    }
+
+    fn set_active_window(&self, window_id: &WindowId) {
+        self.record(Request::SetActive(*window_id));
+    }
+
+    fn get_input_model(&self, window_id: &WindowId) -> InputModel {
+        self.window(window_id, |w| w.input_model)
+            .unwrap_or_default()
+    }
+
+    fn send_take_focus(&self, window_id: &WindowId) {
+        self.record(Request::TakeFocus(*window_id));
+    }
+
+    fn get_event_loop(&self, _watched: &[RawFd]) -> Box<dyn Iterator<Item = Event> + '_> {
+        Box::new(std::iter::from_fn(move || {
+            self.events.borrow_mut().pop_front()
+        }))
+    }
+}